@@ -0,0 +1,118 @@
+//! Assets loaded at startup, and the aseprite sprite atlas
+
+use bevy::prelude::*;
+use bevy_asset_loader::prelude::{
+    AssetCollection, ConfigureLoadingState, LoadingState, LoadingStateAppExt,
+};
+use bevy_common_assets::json::JsonAssetPlugin;
+use bevy_kira_audio::AudioSource;
+
+use crate::Screen;
+use crate::beats::{Beats, BeatsValidation, validate_beats};
+use crate::chart::{Chart, validate_charts};
+use crate::songs::SongLibrary;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_loading_state(
+        LoadingState::new(Screen::Loading)
+            .continue_to_state(Screen::Menu)
+            .load_collection::<StartupAssetHandles>(),
+    )
+    .add_plugins((
+        JsonAssetPlugin::<Beats>::new(&["beats.json"]),
+        JsonAssetPlugin::<SongLibrary>::new(&["songs.json"]),
+        JsonAssetPlugin::<Chart>::new(&["chart.json"]),
+        JsonAssetPlugin::<SpriteAtlas>::new(&["atlas.json"]),
+    ))
+    .init_resource::<BeatsValidation>()
+    .add_systems(Update, (validate_beats, validate_charts));
+}
+
+/// handles to the assets loaded on game start
+#[derive(AssetCollection, Resource)]
+pub(super) struct StartupAssetHandles {
+    #[expect(unused)]
+    #[asset(path = "images/Eroica_Beethoven_title.jpg")]
+    pub(super) eroica_score: Handle<Image>,
+
+    #[asset(path = "library.songs.json")]
+    pub(super) song_library: Handle<SongLibrary>,
+    #[asset(path = "audio/metronome_click.flac")]
+    pub(super) metronome_click: Handle<AudioSource>,
+
+    #[asset(path = "sprites/sprite_sheet.png")]
+    pub(super) sprite_sheet: Handle<Image>,
+    #[asset(path = "sprites/sprite_sheet.atlas.json")]
+    pub(super) sprite_atlas: Handle<SpriteAtlas>,
+}
+
+#[derive(serde::Deserialize, bevy::asset::Asset, bevy::reflect::TypePath)]
+pub(super) struct SpriteAtlas {
+    #[expect(unused)]
+    meta: SpriteAtlasMeta,
+    frames: Vec<SpriteFrame>,
+}
+
+impl SpriteAtlas {
+    pub(super) fn get_offsets_or_panic<'s>(&'s self, name: &str) -> &'s SpriteAtlasFrameOffsets {
+        let frame = self
+            .frames
+            .iter()
+            .find(|f| f.filename.starts_with(name))
+            .unwrap();
+
+        &frame.frame
+    }
+
+    /// the frames of an animation, which aseprite exports as "{name} {frame}"
+    pub(super) fn get_frames<'s>(&'s self, name: &str) -> Vec<&'s SpriteAtlasFrameOffsets> {
+        self.frames
+            .iter()
+            .filter(|f| f.filename.rsplit_once(' ').is_some_and(|(n, _)| n == name))
+            .map(|f| &f.frame)
+            .collect()
+    }
+}
+
+#[expect(unused)]
+#[derive(serde::Deserialize)]
+struct SpriteAtlasMeta {
+    size: SpriteAtlasSize,
+}
+
+#[expect(unused)]
+#[derive(serde::Deserialize)]
+struct SpriteAtlasSize {
+    w: usize,
+    h: usize,
+}
+
+#[derive(serde::Deserialize)]
+struct SpriteFrame {
+    filename: String,
+    frame: SpriteAtlasFrameOffsets,
+    // we don't want to use this literally
+    // but do we still want it for handling proportions?
+    #[expect(unused)]
+    duration: u64,
+}
+
+#[derive(serde::Deserialize)]
+pub(super) struct SpriteAtlasFrameOffsets {
+    x: usize,
+    y: usize,
+    w: usize,
+    h: usize,
+}
+
+impl SpriteAtlasFrameOffsets {
+    pub(super) fn as_rect(&self) -> Rect {
+        let min = Vec2::new(self.x as f32, self.y as f32);
+
+        let max_x = self.x as f32 + self.w as f32;
+        let max_y = self.y as f32 + self.h as f32;
+        let max = Vec2::new(max_x, max_y);
+
+        Rect { min, max }
+    }
+}
@@ -0,0 +1,341 @@
+//! Beat timestamps for each track, and checking them as they load
+
+use bevy::prelude::*;
+
+use crate::calibration::median;
+
+#[derive(serde::Serialize, serde::Deserialize, bevy::asset::Asset, bevy::reflect::TypePath)]
+pub(super) struct Beats {
+    pub(super) bpm: f32,
+    pub(super) beats_confidence: f32,
+    pub(super) beats: Vec<f32>,
+    pub(super) beats_intervals: Vec<f32>,
+    #[serde(default)]
+    pub(super) time_signature: TimeSignature,
+    /// the index of the first downbeat; any beats before it are a pickup
+    #[serde(default)]
+    pub(super) bar_offset: usize,
+    /// Beat indices that start a new bar, overriding the regular pattern from that point on
+    ///
+    /// For fixing up tempo changes, or beats the extraction missed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) downbeats: Vec<usize>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+pub(super) struct TimeSignature {
+    pub(super) beats_per_bar: usize,
+    /// the note value that counts as one beat (4 for quarter notes)
+    beat_unit: usize,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self {
+            beats_per_bar: 4,
+            beat_unit: 4,
+        }
+    }
+}
+
+/// bpm outside this range is assumed to be a misdetection
+const BEATS_BPM_RANGE: std::ops::RangeInclusive<f32> = 30.0..=300.0;
+/// gaps longer than this many typical beat intervals are treated as missed beats
+const BEATS_MAX_GAP: f32 = 1.75;
+/// beat interval used when a beats file has nothing better to offer (120 bpm)
+const FALLBACK_BEAT_INTERVAL: f32 = 0.5;
+
+/// A problem found in a beats.json asset
+#[derive(Debug, Clone, PartialEq)]
+enum BeatsIssue {
+    Empty,
+    /// negative, NaN or infinite timestamps
+    InvalidTimestamps(usize),
+    /// timestamps that are earlier than (or equal to) the one before them
+    NonMonotonic(usize),
+    Gap {
+        start: f32,
+        duration: f32,
+    },
+    BpmOutOfRange(f32),
+    /// a time signature with zero beats per bar
+    EmptyBars,
+    UnsortedDownbeats,
+    /// intervals that don't match the timestamps
+    StaleIntervals,
+}
+
+impl std::fmt::Display for BeatsIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no beats"),
+            Self::InvalidTimestamps(count) => write!(f, "{count} invalid timestamps"),
+            Self::NonMonotonic(count) => write!(f, "{count} timestamps out of order"),
+            Self::Gap { start, duration } => write!(f, "{duration:.2}s gap after {start:.2}s"),
+            Self::BpmOutOfRange(bpm) => write!(f, "bpm of {bpm} is out of range"),
+            Self::EmptyBars => write!(f, "time signature has no beats per bar"),
+            Self::UnsortedDownbeats => write!(f, "downbeats out of order"),
+            Self::StaleIntervals => write!(f, "intervals don't match timestamps"),
+        }
+    }
+}
+
+impl Beats {
+    /// the signed offset (in seconds) from the beat nearest to the given track time
+    pub(super) fn nearest_beat_offset(&self, track_time: f32) -> Option<f32> {
+        let next_index = self.beats.partition_point(|&beat| beat < track_time);
+        let next = self.beats.get(next_index);
+        let prev = next_index.checked_sub(1).and_then(|i| self.beats.get(i));
+
+        [prev, next]
+            .into_iter()
+            .flatten()
+            .map(|&beat| track_time - beat)
+            .min_by(|a, b| a.abs().total_cmp(&b.abs()))
+    }
+
+    /// The time of the given beat, continuing at the final tempo past the last beat
+    /// so the beat clock keeps running through the end of the track
+    pub(super) fn beat_time(&self, beat_index: usize) -> f32 {
+        if let Some(&beat) = self.beats.get(beat_index) {
+            return beat;
+        }
+
+        let interval = self.typical_interval();
+        match self.beats.len().checked_sub(1) {
+            Some(last_index) => {
+                self.beats[last_index] + (beat_index - last_index) as f32 * interval
+            }
+            None => beat_index as f32 * interval,
+        }
+    }
+
+    /// the median time between beats
+    fn typical_interval(&self) -> f32 {
+        let intervals: Vec<f32> = self
+            .beats
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .filter(|interval| interval.is_finite() && *interval > 0.0)
+            .collect();
+        if !intervals.is_empty() {
+            return median(&intervals);
+        }
+
+        if self.bpm.is_finite() && BEATS_BPM_RANGE.contains(&self.bpm) {
+            60.0 / self.bpm
+        } else {
+            FALLBACK_BEAT_INTERVAL
+        }
+    }
+
+    fn validate(&self) -> Vec<BeatsIssue> {
+        let mut issues = Vec::new();
+
+        if self.beats.is_empty() {
+            issues.push(BeatsIssue::Empty);
+            return issues;
+        }
+
+        let invalid = self
+            .beats
+            .iter()
+            .filter(|beat| !beat.is_finite() || **beat < 0.0)
+            .count();
+        if invalid > 0 {
+            issues.push(BeatsIssue::InvalidTimestamps(invalid));
+        }
+
+        let out_of_order = self
+            .beats
+            .windows(2)
+            .filter(|pair| pair[1] <= pair[0])
+            .count();
+        if out_of_order > 0 {
+            issues.push(BeatsIssue::NonMonotonic(out_of_order));
+        }
+
+        let typical_interval = self.typical_interval();
+        for pair in self.beats.windows(2) {
+            let duration = pair[1] - pair[0];
+            if duration > typical_interval * BEATS_MAX_GAP {
+                issues.push(BeatsIssue::Gap {
+                    start: pair[0],
+                    duration,
+                });
+            }
+        }
+
+        if !self.bpm.is_finite() || !BEATS_BPM_RANGE.contains(&self.bpm) {
+            issues.push(BeatsIssue::BpmOutOfRange(self.bpm));
+        }
+
+        if self.time_signature.beats_per_bar == 0 {
+            issues.push(BeatsIssue::EmptyBars);
+        }
+
+        if !self.downbeats.is_sorted_by(|a, b| a < b) {
+            issues.push(BeatsIssue::UnsortedDownbeats);
+        }
+
+        let stale_intervals = self.beats_intervals.len() != self.beats.len() - 1
+            || self
+                .beats
+                .windows(2)
+                .zip(&self.beats_intervals)
+                .any(|(pair, interval)| (pair[1] - pair[0] - interval).abs() > 0.001);
+        if stale_intervals {
+            issues.push(BeatsIssue::StaleIntervals);
+        }
+
+        issues
+    }
+
+    /// Which bar the given beat falls in, and which beat of that bar it is
+    ///
+    /// Bars are counted from 1 at the first downbeat, so a pickup is bar 0.
+    pub(super) fn bar_position(&self, beat_index: usize) -> (usize, usize) {
+        let beats_per_bar = self.time_signature.beats_per_bar.max(1);
+
+        // the regular pattern, before any downbeat markers
+        let regular = |beat: usize| {
+            if beat < self.bar_offset {
+                let beats_before_downbeat = (self.bar_offset - beat) % beats_per_bar;
+                (0, (beats_per_bar - beats_before_downbeat) % beats_per_bar)
+            } else {
+                let beats_since_downbeat = beat - self.bar_offset;
+                (
+                    1 + beats_since_downbeat / beats_per_bar,
+                    beats_since_downbeat % beats_per_bar,
+                )
+            }
+        };
+
+        let markers_before = self
+            .downbeats
+            .partition_point(|&downbeat| downbeat <= beat_index);
+        let Some(&first_marker) = self.downbeats.first() else {
+            return regular(beat_index);
+        };
+        if markers_before == 0 {
+            return regular(beat_index);
+        }
+
+        // a marker partway through a bar cuts that bar short
+        let (bar, beat_in_bar) = regular(first_marker);
+        let mut marker_bar = if beat_in_bar == 0 { bar } else { bar + 1 };
+        for pair in self.downbeats[..markers_before].windows(2) {
+            marker_bar += (pair[1] - pair[0]).div_ceil(beats_per_bar);
+        }
+
+        let marker = self.downbeats[markers_before - 1];
+        let beats_since_marker = beat_index - marker;
+        (
+            marker_bar + beats_since_marker / beats_per_bar,
+            beats_since_marker % beats_per_bar,
+        )
+    }
+
+    /// Fixes everything `validate` reports, except for having no beats at all
+    fn repair(&mut self) {
+        self.beats.retain(|beat| beat.is_finite() && *beat >= 0.0);
+        self.beats.sort_by(f32::total_cmp);
+        self.beats.dedup();
+
+        // fill gaps with evenly spaced beats
+        let typical_interval = self.typical_interval();
+        let mut repaired = Vec::with_capacity(self.beats.len());
+        // where each beat ended up, to keep bar markers on the same beats
+        let mut new_indices = Vec::with_capacity(self.beats.len());
+        for (i, &beat) in self.beats.iter().enumerate() {
+            new_indices.push(repaired.len());
+            repaired.push(beat);
+
+            let Some(&next) = self.beats.get(i + 1) else {
+                continue;
+            };
+            let gap = next - beat;
+            if gap <= typical_interval * BEATS_MAX_GAP {
+                continue;
+            }
+
+            let missing = (gap / typical_interval).round() as usize - 1;
+            let spacing = gap / (missing + 1) as f32;
+            repaired.extend((1..=missing).map(|n| beat + n as f32 * spacing));
+        }
+        let inserted = repaired.len() - self.beats.len();
+        let remap = |index: usize| new_indices.get(index).copied().unwrap_or(index + inserted);
+        self.bar_offset = remap(self.bar_offset);
+        for downbeat in &mut self.downbeats {
+            *downbeat = remap(*downbeat);
+        }
+        self.downbeats.sort();
+        self.downbeats.dedup();
+
+        self.beats = repaired;
+        self.beats_intervals = self
+            .beats
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect();
+
+        if !self.bpm.is_finite() || !BEATS_BPM_RANGE.contains(&self.bpm) {
+            let bpm = 60.0 / self.typical_interval();
+            self.bpm = bpm.clamp(*BEATS_BPM_RANGE.start(), *BEATS_BPM_RANGE.end());
+        }
+
+        if self.time_signature.beats_per_bar == 0 {
+            self.time_signature = TimeSignature::default();
+        }
+    }
+}
+
+/// How beats.json assets are checked as they load
+#[derive(Resource)]
+pub(super) struct BeatsValidation {
+    /// whether to fix problems in place, rather than only reporting them
+    repair: bool,
+}
+
+impl Default for BeatsValidation {
+    fn default() -> Self {
+        Self { repair: true }
+    }
+}
+
+/// Reports (and optionally repairs) problems in beats assets as they're loaded or edited
+pub(super) fn validate_beats(
+    validation: Res<BeatsValidation>,
+    mut asset_events: MessageReader<AssetEvent<Beats>>,
+    mut beats_assets: ResMut<Assets<Beats>>,
+) {
+    for event in asset_events.read() {
+        let id = match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => *id,
+            _ => continue,
+        };
+
+        let Some(beats) = beats_assets.get(id) else {
+            continue;
+        };
+
+        let issues = beats.validate();
+        if issues.is_empty() {
+            continue;
+        }
+
+        for issue in &issues {
+            log::warn!("beats {id}: {issue}");
+        }
+
+        // there's nothing to rebuild from
+        if !validation.repair || issues == [BeatsIssue::Empty] {
+            continue;
+        }
+
+        // only borrow mutably when there's something to fix, since that sends another Modified event
+        let beats = beats_assets.get_mut(id).unwrap();
+        beats.repair();
+        log::info!("repaired beats {id}");
+    }
+}
@@ -0,0 +1,652 @@
+//! Writes `*.beats.json` files next to `*.flac` files, with automatically extracted beat timestamps
+//!
+//! usage: `cargo run --bin extract_beats -- [flac files or directories...]`
+//!
+//! Hand-authored fields in an existing file (the time signature, bar offset and downbeat markers)
+//! are kept; everything else is replaced.
+//!
+//! Onsets are detected with spectral flux, the tempo is estimated by autocorrelating the onsets,
+//! and beats are placed with dynamic programming, following
+//! Ellis, "Beat Tracking by Dynamic Programming" (2007).
+
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CODEC_TYPE_NULL, DecoderOptions};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+const DEFAULT_AUDIO_DIR: &str = "assets/audio";
+
+/// samples per analysis frame; must be a power of two
+const FRAME_SIZE: usize = 1024;
+/// samples between the starts of analysis frames
+const HOP_SIZE: usize = 256;
+
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+/// the tempo preferred when autocorrelation is ambiguous between multiples
+const PRIOR_BPM: f32 = 120.0;
+/// how strongly beat spacing is held to the estimated tempo
+const TIGHTNESS: f32 = 100.0;
+
+/// fields of the game's `Beats` asset that are set by hand or in the editor, not extracted
+const AUTHORED_FIELDS: [&str; 3] = ["time_signature", "bar_offset", "downbeats"];
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// the same shape as the game's `Beats` asset
+#[derive(Serialize, Debug)]
+struct BeatsJson {
+    bpm: f32,
+    /// the fraction of beats that land on a stronger than average onset, 0.0-1.0
+    beats_confidence: f32,
+    beats: Vec<f32>,
+    beats_intervals: Vec<f32>,
+    /// [`AUTHORED_FIELDS`] carried over from the file being replaced
+    #[serde(flatten)]
+    authored: Map<String, Value>,
+}
+
+fn main() -> Result<()> {
+    let mut args: Vec<PathBuf> = std::env::args_os().skip(1).map(PathBuf::from).collect();
+    if args.is_empty() {
+        args.push(PathBuf::from(DEFAULT_AUDIO_DIR));
+    }
+
+    let mut flac_paths = Vec::new();
+    for arg in &args {
+        find_flac_files(arg, &mut flac_paths)?;
+    }
+
+    if flac_paths.is_empty() {
+        return Err("no flac files found".into());
+    }
+
+    for flac_path in flac_paths {
+        let json_path = flac_path.with_extension("beats.json");
+        println!("{} -> {}", flac_path.display(), json_path.display());
+
+        let (samples, sample_rate) = decode_mono(&flac_path)?;
+        let beats = extract_beats(&samples, sample_rate);
+        println!("  {:.1} bpm, {} beats", beats.bpm, beats.beats.len());
+
+        update_beats_json(&json_path, beats)?;
+    }
+
+    Ok(())
+}
+
+fn find_flac_files(path: &Path, found: &mut Vec<PathBuf>) -> Result<()> {
+    if path.is_dir() {
+        let mut entries = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort();
+
+        for entry in entries {
+            find_flac_files(&entry, found)?;
+        }
+    } else if path.extension().is_some_and(|ext| ext == "flac") {
+        found.push(path.to_path_buf());
+    }
+
+    Ok(())
+}
+
+/// decodes an audio file, averaging all channels together
+fn decode_mono(path: &Path) -> Result<(Vec<f32>, u32)> {
+    let file = File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or("no audio track")?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or("unknown sample rate")?;
+
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut samples = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = decoder.decode(&packet)?;
+        let spec = *decoded.spec();
+        let channels = spec.channels.count();
+
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+
+        for frame in buffer.samples().chunks(channels) {
+            samples.push(frame.iter().sum::<f32>() / channels as f32);
+        }
+    }
+
+    Ok((samples, sample_rate))
+}
+
+/// Writes freshly extracted beats over a beats file, keeping any authored fields it already had
+fn update_beats_json(path: &Path, mut beats: BeatsJson) -> Result<()> {
+    beats.authored = read_authored_fields(path)?;
+    if !beats.authored.is_empty() {
+        let kept: Vec<&str> = beats.authored.keys().map(String::as_str).collect();
+        println!("  kept {}", kept.join(", "));
+    }
+
+    write_json(path, &beats)
+}
+
+fn read_authored_fields(path: &Path) -> Result<Map<String, Value>> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Map::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    // refuse to overwrite a file that can't be read, rather than losing what's in it
+    let existing: Map<String, Value> = serde_json::from_str(&json)
+        .map_err(|e| format!("couldn't read existing {}: {e}", path.display()))?;
+
+    Ok(existing
+        .into_iter()
+        .filter(|(key, _)| AUTHORED_FIELDS.contains(&key.as_str()))
+        .collect())
+}
+
+fn write_json(path: &Path, beats: &BeatsJson) -> Result<()> {
+    // match the indentation of the existing assets
+    let mut json = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut json, formatter);
+    beats.serialize(&mut serializer)?;
+    json.push(b'\n');
+
+    std::fs::write(path, json)?;
+
+    Ok(())
+}
+
+fn extract_beats(samples: &[f32], sample_rate: u32) -> BeatsJson {
+    let frame_rate = sample_rate as f32 / HOP_SIZE as f32;
+    let onsets = onset_envelope(samples);
+    let period = estimate_period(&onsets, frame_rate);
+    let beat_frames = track_beats(&onsets, period);
+
+    let beats: Vec<f32> = beat_frames
+        .iter()
+        .map(|&frame| frame_time(frame, sample_rate))
+        .collect();
+    let beats_intervals: Vec<f32> = beats.windows(2).map(|pair| pair[1] - pair[0]).collect();
+
+    let bpm = match median(&beats_intervals) {
+        Some(interval) if interval > 0.0 => 60.0 / interval,
+        _ => 60.0 * frame_rate / period,
+    };
+
+    let mean_onset = onsets.iter().sum::<f32>() / onsets.len().max(1) as f32;
+    let strong_beats = beat_frames
+        .iter()
+        .filter(|&&frame| onsets[frame] > mean_onset)
+        .count();
+    let beats_confidence = strong_beats as f32 / beat_frames.len().max(1) as f32;
+
+    BeatsJson {
+        bpm,
+        beats_confidence,
+        beats,
+        beats_intervals,
+        authored: Map::new(),
+    }
+}
+
+/// The time (in seconds) an onset in the given frame is reported at
+///
+/// Spectral flux peaks as an onset enters the end of the window,
+/// so this is the middle of the last hop in the frame.
+fn frame_time(frame: usize, sample_rate: u32) -> f32 {
+    let sample = frame * HOP_SIZE + FRAME_SIZE - HOP_SIZE / 2;
+    sample as f32 / sample_rate as f32
+}
+
+fn median(values: &[f32]) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    Some(sorted[sorted.len() / 2])
+}
+
+/// Spectral flux per frame: the summed increase in log magnitude across frequency bins,
+/// detrended and normalized to unit standard deviation
+fn onset_envelope(samples: &[f32]) -> Vec<f32> {
+    let window: Vec<f32> = (0..FRAME_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FRAME_SIZE as f32).cos())
+        .collect();
+    let fft = Fft::new(FRAME_SIZE);
+
+    let bins = FRAME_SIZE / 2 + 1;
+    let mut prev_magnitudes = vec![0.0; bins];
+    let mut buffer = vec![Complex::ZERO; FRAME_SIZE];
+    let mut flux = Vec::new();
+
+    let frame_count = samples.len().saturating_sub(FRAME_SIZE) / HOP_SIZE + 1;
+    for frame in 0..frame_count {
+        let start = frame * HOP_SIZE;
+        for (i, value) in buffer.iter_mut().enumerate() {
+            let sample = samples.get(start + i).copied().unwrap_or(0.0);
+            *value = Complex::new(sample * window[i], 0.0);
+        }
+
+        fft.transform(&mut buffer);
+
+        let mut frame_flux = 0.0;
+        for (bin, prev_magnitude) in prev_magnitudes.iter_mut().enumerate() {
+            let magnitude = (1.0 + 1000.0 * buffer[bin].norm()).ln();
+            frame_flux += (magnitude - *prev_magnitude).max(0.0);
+            *prev_magnitude = magnitude;
+        }
+
+        // the first frame has nothing to compare against
+        flux.push(if frame == 0 { 0.0 } else { frame_flux });
+    }
+
+    // subtract a local average, so only peaks remain
+    let radius = 8;
+    let mut envelope: Vec<f32> = (0..flux.len())
+        .map(|i| {
+            let neighbors = &flux[i.saturating_sub(radius)..(i + radius + 1).min(flux.len())];
+            let local_mean = neighbors.iter().sum::<f32>() / neighbors.len() as f32;
+            (flux[i] - local_mean).max(0.0)
+        })
+        .collect();
+
+    let mean = envelope.iter().sum::<f32>() / envelope.len().max(1) as f32;
+    let variance =
+        envelope.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / envelope.len().max(1) as f32;
+    let std_dev = variance.sqrt();
+    if std_dev > 0.0 {
+        for value in &mut envelope {
+            *value /= std_dev;
+        }
+    }
+
+    envelope
+}
+
+/// Estimates the beat period (in frames) by autocorrelating the onset envelope,
+/// weighted towards `PRIOR_BPM` to choose between tempo multiples
+fn estimate_period(onsets: &[f32], frame_rate: f32) -> f32 {
+    let min_lag = ((60.0 / MAX_BPM * frame_rate).floor() as usize).max(1);
+    let max_lag =
+        ((60.0 / MIN_BPM * frame_rate).ceil() as usize).min(onsets.len().saturating_sub(1));
+    if min_lag >= max_lag {
+        return 60.0 / PRIOR_BPM * frame_rate;
+    }
+
+    let autocorrelation = |lag: usize| -> f32 {
+        let pairs = onsets.len() - lag;
+        let sum: f32 = onsets.iter().zip(&onsets[lag..]).map(|(a, b)| a * b).sum();
+        sum / pairs as f32
+    };
+
+    let weighted = |lag: usize| -> f32 {
+        let bpm = 60.0 * frame_rate / lag as f32;
+        let octaves_from_prior = (bpm / PRIOR_BPM).log2();
+        autocorrelation(lag) * (-0.5 * octaves_from_prior.powi(2)).exp()
+    };
+
+    let best_lag = (min_lag..=max_lag)
+        .max_by(|&a, &b| weighted(a).total_cmp(&weighted(b)))
+        .unwrap();
+
+    // refine between frames with a parabola through the neighboring lags
+    if best_lag <= min_lag || best_lag >= max_lag {
+        return best_lag as f32;
+    }
+
+    let before = autocorrelation(best_lag - 1);
+    let at = autocorrelation(best_lag);
+    let after = autocorrelation(best_lag + 1);
+    let curvature = before - 2.0 * at + after;
+    if curvature >= 0.0 {
+        return best_lag as f32;
+    }
+
+    let shift = (0.5 * (before - after) / curvature).clamp(-0.5, 0.5);
+    best_lag as f32 + shift
+}
+
+/// Places beats on onsets while keeping them close to the estimated period,
+/// returning the frame of each beat
+fn track_beats(onsets: &[f32], period: f32) -> Vec<usize> {
+    if onsets.is_empty() {
+        return Vec::new();
+    }
+
+    // smooth the onsets slightly, so near misses still count
+    let sigma = (period / 32.0).max(0.5);
+    let radius = (3.0 * sigma).ceil() as isize;
+    let local_score: Vec<f32> = (0..onsets.len() as isize)
+        .map(|i| {
+            (-radius..=radius)
+                .filter_map(|offset| {
+                    let onset = onsets.get(usize::try_from(i + offset).ok()?)?;
+                    let weight = (-0.5 * (offset as f32 / sigma).powi(2)).exp();
+                    Some(onset * weight)
+                })
+                .sum()
+        })
+        .collect();
+
+    let min_interval = (period / 2.0).round().max(1.0) as usize;
+    let max_interval = (period * 2.0).round() as usize;
+
+    let mut score = vec![0.0; onsets.len()];
+    let mut backlink: Vec<Option<usize>> = vec![None; onsets.len()];
+    for t in 0..onsets.len() {
+        let mut best: Option<(f32, usize)> = None;
+        for interval in min_interval..=max_interval.min(t) {
+            let prev = t - interval;
+            let transition_cost = TIGHTNESS * (interval as f32 / period).ln().powi(2);
+            let candidate = score[prev] - transition_cost;
+            if best.is_none_or(|(best_score, _)| candidate > best_score) {
+                best = Some((candidate, prev));
+            }
+        }
+
+        score[t] = local_score[t];
+        if let Some((prev_score, prev)) = best
+            && prev_score > 0.0
+        {
+            score[t] += prev_score;
+            backlink[t] = Some(prev);
+        }
+    }
+
+    // the earliest best score, so trailing silence doesn't add beats
+    let mut last_beat = 0;
+    for (t, &beat_score) in score.iter().enumerate() {
+        if beat_score > score[last_beat] {
+            last_beat = t;
+        }
+    }
+
+    let mut beats = vec![last_beat];
+    while let Some(prev) = backlink[*beats.last().unwrap()] {
+        beats.push(prev);
+    }
+    beats.reverse();
+
+    // drop weak beats at either end, where the tracker is extrapolating
+    let rms =
+        (beats.iter().map(|&b| local_score[b].powi(2)).sum::<f32>() / beats.len() as f32).sqrt();
+    let threshold = 0.5 * rms;
+    let first = beats.iter().position(|&b| local_score[b] >= threshold);
+    let last = beats.iter().rposition(|&b| local_score[b] >= threshold);
+    match (first, last) {
+        (Some(first), Some(last)) => beats[first..=last].to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    const ZERO: Self = Self::new(0.0, 0.0);
+
+    const fn new(re: f32, im: f32) -> Self {
+        Self { re, im }
+    }
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.re + other.re, self.im + other.im)
+    }
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.re - other.re, self.im - other.im)
+    }
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+
+    fn norm(self) -> f32 {
+        self.re.hypot(self.im)
+    }
+}
+
+/// An in-place radix-2 FFT with precomputed twiddle factors
+struct Fft {
+    size: usize,
+    twiddles: Vec<Complex>,
+}
+
+impl Fft {
+    fn new(size: usize) -> Self {
+        assert!(size.is_power_of_two());
+
+        let twiddles = (0..size / 2)
+            .map(|k| {
+                let angle = -2.0 * PI * k as f32 / size as f32;
+                Complex::new(angle.cos(), angle.sin())
+            })
+            .collect();
+
+        Self { size, twiddles }
+    }
+
+    fn transform(&self, buffer: &mut [Complex]) {
+        assert_eq!(buffer.len(), self.size);
+
+        // reorder by bit-reversed index
+        let mut j = 0;
+        for i in 1..self.size {
+            let mut bit = self.size >> 1;
+            while j & bit != 0 {
+                j ^= bit;
+                bit >>= 1;
+            }
+            j |= bit;
+
+            if i < j {
+                buffer.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= self.size {
+            let twiddle_step = self.size / len;
+            for start in (0..self.size).step_by(len) {
+                for k in 0..len / 2 {
+                    let even = buffer[start + k];
+                    let odd = buffer[start + k + len / 2].mul(self.twiddles[k * twiddle_step]);
+                    buffer[start + k] = even.add(odd);
+                    buffer[start + k + len / 2] = even.sub(odd);
+                }
+            }
+
+            len <<= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 22050;
+
+    /// short decaying 1kHz blips at a steady tempo
+    fn click_track(bpm: f32, first_click: f32, seconds: f32) -> (Vec<f32>, Vec<f32>) {
+        let sample_rate = SAMPLE_RATE as f32;
+        let mut samples = vec![0.0; (seconds * sample_rate) as usize];
+        let click_len = (0.02 * sample_rate) as usize;
+
+        let mut clicks = Vec::new();
+        let mut click = first_click;
+        while click < seconds {
+            clicks.push(click);
+
+            let start = (click * sample_rate) as usize;
+            for i in 0..click_len {
+                let Some(sample) = samples.get_mut(start + i) else {
+                    break;
+                };
+
+                let t = i as f32 / sample_rate;
+                *sample = (2.0 * PI * 1000.0 * t).sin() * (-t / 0.003).exp();
+            }
+
+            click += 60.0 / bpm;
+        }
+
+        (samples, clicks)
+    }
+
+    #[test]
+    fn fft_matches_naive_dft() {
+        let size = 16;
+        let input: Vec<Complex> = (0..size)
+            .map(|i| Complex::new((i as f32 * 0.7).sin() + 0.25 * i as f32, 0.0))
+            .collect();
+
+        let mut output = input.clone();
+        Fft::new(size).transform(&mut output);
+
+        for (k, actual) in output.iter().enumerate() {
+            let expected = input
+                .iter()
+                .enumerate()
+                .fold(Complex::ZERO, |sum, (n, value)| {
+                    let angle = -2.0 * PI * (k * n) as f32 / size as f32;
+                    sum.add(value.mul(Complex::new(angle.cos(), angle.sin())))
+                });
+
+            assert!((actual.re - expected.re).abs() < 1e-3, "bin {k}");
+            assert!((actual.im - expected.im).abs() < 1e-3, "bin {k}");
+        }
+    }
+
+    #[test]
+    fn detects_click_track_tempo() {
+        let (samples, _) = click_track(120.0, 0.5, 20.0);
+        let beats = extract_beats(&samples, SAMPLE_RATE);
+
+        assert!((beats.bpm - 120.0).abs() < 1.5, "bpm was {}", beats.bpm);
+    }
+
+    #[test]
+    fn beats_land_on_clicks() {
+        let (samples, clicks) = click_track(95.0, 0.3, 20.0);
+        let beats = extract_beats(&samples, SAMPLE_RATE);
+
+        for beat in &beats.beats {
+            let nearest_click = clicks
+                .iter()
+                .map(|click| (click - beat).abs())
+                .min_by(f32::total_cmp)
+                .unwrap();
+            assert!(
+                nearest_click < 0.035,
+                "beat at {beat} is {nearest_click}s off"
+            );
+        }
+
+        // the ends are allowed to be trimmed, but not much else
+        assert!(
+            beats.beats.len() + 2 >= clicks.len(),
+            "found {} beats for {} clicks",
+            beats.beats.len(),
+            clicks.len(),
+        );
+    }
+
+    #[test]
+    fn output_matches_beats_asset_shape() {
+        let (samples, _) = click_track(100.0, 0.5, 10.0);
+        let beats = extract_beats(&samples, SAMPLE_RATE);
+        let json = serde_json::to_value(&beats).unwrap();
+
+        for key in ["bpm", "beats_confidence", "beats", "beats_intervals"] {
+            assert!(json.get(key).is_some(), "missing {key}");
+        }
+        assert_eq!(beats.beats_intervals.len(), beats.beats.len() - 1);
+    }
+
+    #[test]
+    fn authored_fields_survive_re_extraction() {
+        let dir = std::env::temp_dir().join(format!("extract_beats_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("song.beats.json");
+
+        let authored = serde_json::json!({
+            "time_signature": { "beats_per_bar": 3, "beat_unit": 4 },
+            "bar_offset": 1,
+            "downbeats": [7, 12],
+        });
+        let mut existing = authored.clone();
+        existing["bpm"] = 90.0.into();
+        existing["beats"] = serde_json::json!([0.1, 0.2]);
+        std::fs::write(&path, existing.to_string()).unwrap();
+
+        let (samples, _) = click_track(100.0, 0.5, 10.0);
+        update_beats_json(&path, extract_beats(&samples, SAMPLE_RATE)).unwrap();
+
+        let written: Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        for key in AUTHORED_FIELDS {
+            assert_eq!(written[key], authored[key], "{key} changed");
+        }
+        assert!((written["bpm"].as_f64().unwrap() - 100.0).abs() < 1.5);
+        assert!(written["beats"].as_array().unwrap().len() > 2);
+    }
+}
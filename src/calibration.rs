@@ -0,0 +1,259 @@
+//! Latency calibration
+
+use bevy::prelude::*;
+use bevy_kira_audio::{Audio, AudioControl};
+
+use crate::Screen;
+use crate::assets::StartupAssetHandles;
+use crate::input::{ActionInput, Binding};
+use crate::settings::Settings;
+use crate::ui::{menu_button, return_to_menu};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(OnEnter(Screen::Calibration), start_calibration)
+        .add_systems(
+            Update,
+            run_calibration.run_if(in_state(Screen::Calibration)),
+        );
+}
+
+/// seconds between metronome clicks (100 bpm)
+const CALIBRATION_INTERVAL: f32 = 0.6;
+/// clicks per phase
+const CALIBRATION_CLICKS: usize = 16;
+/// clicks at the start of each phase to let the player find the tempo
+const CALIBRATION_LEAD_IN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CalibrationPhase {
+    /// tapping along to an audible click, which measures audio + input latency
+    Listen,
+    /// tapping along to a silent flash, which measures input latency alone
+    Watch,
+    Done,
+}
+
+#[derive(Resource)]
+struct Calibration {
+    phase: CalibrationPhase,
+    /// seconds since the current phase started
+    elapsed: f32,
+    /// the next click to play/flash in the current phase
+    next_click: usize,
+    /// offsets (in seconds) of each tap from its nearest click
+    listen_offsets: Vec<f32>,
+    watch_offsets: Vec<f32>,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            phase: CalibrationPhase::Listen,
+            elapsed: 0.0,
+            next_click: 0,
+            listen_offsets: Vec::new(),
+            watch_offsets: Vec::new(),
+        }
+    }
+}
+
+impl Calibration {
+    fn record_tap(&mut self) {
+        let nearest_click = (self.elapsed / CALIBRATION_INTERVAL).round() as usize;
+        if !(CALIBRATION_LEAD_IN..CALIBRATION_CLICKS).contains(&nearest_click) {
+            return;
+        }
+
+        let offset = self.elapsed - nearest_click as f32 * CALIBRATION_INTERVAL;
+        match self.phase {
+            CalibrationPhase::Listen => self.listen_offsets.push(offset),
+            CalibrationPhase::Watch => self.watch_offsets.push(offset),
+            CalibrationPhase::Done => {}
+        }
+    }
+
+    fn next_phase(&mut self) {
+        self.phase = match self.phase {
+            CalibrationPhase::Listen => CalibrationPhase::Watch,
+            CalibrationPhase::Watch | CalibrationPhase::Done => CalibrationPhase::Done,
+        };
+        self.elapsed = 0.0;
+        self.next_click = 0;
+    }
+
+    /// the input offset, and the audio offset on top of it,
+    /// or nothing if either phase went without any taps to measure
+    fn offsets(&self) -> Option<(f32, f32)> {
+        if self.listen_offsets.is_empty() || self.watch_offsets.is_empty() {
+            return None;
+        }
+
+        let input_offset = median(&self.watch_offsets);
+        let audio_offset = median(&self.listen_offsets) - input_offset;
+
+        Some((input_offset, audio_offset))
+    }
+}
+
+pub(super) fn median(values: &[f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    sorted[sorted.len() / 2]
+}
+
+#[derive(Component)]
+struct CalibrationText;
+
+#[derive(Component)]
+struct CalibrationPulse;
+
+const CALIBRATION_PULSE_IDLE: Color = Color::srgb(0.25, 0.25, 0.25);
+const CALIBRATION_PULSE_FLASH: Color = Color::srgb(0.95, 0.95, 0.95);
+
+fn start_calibration(mut commands: Commands) {
+    commands.insert_resource(Calibration::default());
+
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(40.0),
+            ..default()
+        },
+        GlobalZIndex(2),
+        DespawnOnExit(Screen::Calibration),
+        Pickable::IGNORE,
+        children![
+            (
+                CalibrationText,
+                Text::new(""),
+                TextFont {
+                    font_size: 32.0,
+                    ..default()
+                },
+                TextLayout::new_with_justify(Justify::Center),
+                TextColor(Color::srgb(0.15, 0.15, 0.15)),
+            ),
+            (
+                CalibrationPulse,
+                Node {
+                    width: Val::Px(80.0),
+                    height: Val::Px(80.0),
+                    border_radius: BorderRadius::MAX,
+                    ..default()
+                },
+                BackgroundColor(CALIBRATION_PULSE_IDLE),
+            ),
+        ],
+    ));
+}
+
+fn run_calibration(
+    time: Res<Time>,
+    input: ActionInput,
+    assets: Res<StartupAssetHandles>,
+    audio: Res<Audio>,
+    mut calibration: ResMut<Calibration>,
+    mut settings: ResMut<Settings>,
+    mut texts: Query<&mut Text, With<CalibrationText>>,
+    mut pulses: Query<(Entity, &ChildOf, &mut BackgroundColor), With<CalibrationPulse>>,
+    mut commands: Commands,
+) {
+    if calibration.phase == CalibrationPhase::Done {
+        return;
+    }
+
+    calibration.elapsed += time.delta_secs();
+
+    let tapped = input.just_pressed(&settings.bindings.scribble) || input.tapped();
+    if tapped {
+        calibration.record_tap();
+    }
+
+    let click_time = calibration.next_click as f32 * CALIBRATION_INTERVAL;
+    let clicked = calibration.next_click < CALIBRATION_CLICKS && calibration.elapsed >= click_time;
+    if clicked {
+        calibration.next_click += 1;
+        if calibration.phase == CalibrationPhase::Listen {
+            audio.play(assets.metronome_click.clone());
+        }
+    }
+
+    // flash briefly on each click while watching
+    let since_click = calibration.elapsed % CALIBRATION_INTERVAL;
+    let flashing = calibration.phase == CalibrationPhase::Watch && since_click < 0.1;
+    for (_, _, mut color) in &mut pulses {
+        color.0 = if flashing {
+            CALIBRATION_PULSE_FLASH
+        } else {
+            CALIBRATION_PULSE_IDLE
+        };
+    }
+
+    let phase_end = CALIBRATION_CLICKS as f32 * CALIBRATION_INTERVAL;
+    if calibration.elapsed > phase_end {
+        calibration.next_phase();
+    }
+
+    let Ok(mut text) = texts.single_mut() else {
+        return;
+    };
+
+    match calibration.phase {
+        CalibrationPhase::Listen => {
+            let hint = tap_hint(&settings.bindings.scribble);
+            text.0 = format!("Tap along to the clicks\n({hint})");
+        }
+        CalibrationPhase::Watch => {
+            text.0 = "Now tap along to the flashes".to_string();
+        }
+        CalibrationPhase::Done => {
+            let result = match calibration.offsets() {
+                Some((input_offset, audio_offset)) => {
+                    settings.input_offset = input_offset;
+                    settings.audio_offset = audio_offset;
+                    settings.save();
+                    "Calibrated"
+                }
+                // keep the last calibration rather than saving offsets of 0
+                None => "Not enough taps, kept the previous offsets",
+            };
+
+            text.0 = format!(
+                "{result}\naudio offset: {:.0}ms\ninput offset: {:.0}ms",
+                settings.audio_offset * 1000.0,
+                settings.input_offset * 1000.0,
+            );
+
+            // replace the pulse with a way back
+            for (pulse, child_of, _) in &pulses {
+                commands.entity(pulse).despawn();
+                commands
+                    .spawn((menu_button("Done"), ChildOf(child_of.parent())))
+                    .observe(return_to_menu);
+            }
+        }
+    }
+}
+
+/// the inputs that count as a tap: whatever scribble is bound to, and the touchscreen
+fn tap_hint(bindings: &[Binding]) -> String {
+    let bound = bindings
+        .iter()
+        .map(|binding| binding.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if bound.is_empty() {
+        "tap".to_string()
+    } else {
+        format!("{bound} or tap")
+    }
+}
@@ -0,0 +1,285 @@
+//! Charts: designed sequences of timed events for a song
+
+use bevy::prelude::*;
+
+use crate::assets::{SpriteAtlas, StartupAssetHandles};
+use crate::enemies::{EnemyKind, spawn_enemy};
+use crate::songs::CurrentSong;
+use crate::timing::{BeatIndex, MusicalPosition, Subdivision};
+
+/// A designed sequence of timed events for a song, listed in a `*.chart.json`
+#[derive(serde::Serialize, serde::Deserialize, bevy::asset::Asset, bevy::reflect::TypePath)]
+pub(super) struct Chart {
+    /// sorted by beat, then off-beat
+    pub(super) events: Vec<ChartEvent>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub(super) struct ChartEvent {
+    /// the beat index the event happens on
+    pub(super) beat: usize,
+    /// plays partway through the beat, rather than on it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) off_beat: Option<OffBeat>,
+    #[serde(flatten)]
+    pub(super) kind: ChartEventKind,
+}
+
+impl ChartEvent {
+    /// the beat index plus how far through the beat the event is
+    pub(super) fn beat_position(&self) -> f32 {
+        let fraction = self.off_beat.map_or(0.0, |off_beat| {
+            off_beat.step as f32 / off_beat.subdivision.steps_per_beat() as f32
+        });
+
+        self.beat as f32 + fraction
+    }
+}
+
+/// A problem found in a chart.json asset
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum ChartIssue {
+    /// events listed before ones that happen earlier
+    UnsortedEvents(usize),
+    /// off-beat steps past the end of their beat
+    OffBeatOutOfRange(usize),
+    /// tempo changes that would never step
+    ZeroTempo(usize),
+    /// negative, NaN or infinite intensities
+    InvalidIntensity(usize),
+}
+
+impl std::fmt::Display for ChartIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnsortedEvents(count) => write!(f, "{count} events out of order"),
+            Self::OffBeatOutOfRange(count) => {
+                write!(f, "{count} off-beat steps past the end of their beat")
+            }
+            Self::ZeroTempo(count) => write!(f, "{count} tempo changes stepping every 0 beats"),
+            Self::InvalidIntensity(count) => write!(f, "{count} invalid intensities"),
+        }
+    }
+}
+
+impl Chart {
+    pub(super) fn validate(&self) -> Vec<ChartIssue> {
+        let mut issues = Vec::new();
+
+        let unsorted = self
+            .events
+            .windows(2)
+            .filter(|pair| pair[1].beat_position() < pair[0].beat_position())
+            .count();
+        if unsorted > 0 {
+            issues.push(ChartIssue::UnsortedEvents(unsorted));
+        }
+
+        let off_beat_out_of_range = self
+            .events
+            .iter()
+            .filter_map(|event| event.off_beat)
+            .filter(|off_beat| off_beat.step >= off_beat.subdivision.steps_per_beat())
+            .count();
+        if off_beat_out_of_range > 0 {
+            issues.push(ChartIssue::OffBeatOutOfRange(off_beat_out_of_range));
+        }
+
+        let zero_tempo = self
+            .events
+            .iter()
+            .filter(|event| matches!(event.kind, ChartEventKind::Tempo { step_every: 0 }))
+            .count();
+        if zero_tempo > 0 {
+            issues.push(ChartIssue::ZeroTempo(zero_tempo));
+        }
+
+        let invalid_intensity = self
+            .events
+            .iter()
+            .filter(|event| match event.kind {
+                ChartEventKind::Intensity { level } => !level.is_finite() || level < 0.0,
+                _ => false,
+            })
+            .count();
+        if invalid_intensity > 0 {
+            issues.push(ChartIssue::InvalidIntensity(invalid_intensity));
+        }
+
+        issues
+    }
+
+    /// Fixes the problems [`Chart::validate`] finds, leaving events in the order they play
+    pub(super) fn repair(&mut self) {
+        for event in &mut self.events {
+            if let Some(off_beat) = &mut event.off_beat {
+                let steps_per_beat = off_beat.subdivision.steps_per_beat();
+                event.beat += off_beat.step / steps_per_beat;
+                off_beat.step %= steps_per_beat;
+            }
+
+            match &mut event.kind {
+                ChartEventKind::Tempo { step_every } => *step_every = (*step_every).max(1),
+                ChartEventKind::Intensity { level } if !level.is_finite() || *level < 0.0 => {
+                    *level = 1.0;
+                }
+                _ => {}
+            }
+        }
+
+        // stable, so events at the same time keep their authored order
+        self.events
+            .sort_by(|a, b| a.beat_position().total_cmp(&b.beat_position()));
+    }
+}
+
+/// Reports and repairs problems in charts as they're loaded or edited,
+/// since playback walks the events in order and would stall on one that's out of place
+pub(super) fn validate_charts(
+    mut asset_events: MessageReader<AssetEvent<Chart>>,
+    mut charts: ResMut<Assets<Chart>>,
+) {
+    for event in asset_events.read() {
+        let id = match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => *id,
+            _ => continue,
+        };
+
+        let Some(chart) = charts.get(id) else {
+            continue;
+        };
+
+        let issues = chart.validate();
+        if issues.is_empty() {
+            continue;
+        }
+
+        for issue in &issues {
+            log::warn!("chart {id}: {issue}");
+        }
+
+        // only borrow mutably when there's something to fix, since that sends another Modified event
+        charts.get_mut(id).unwrap().repair();
+        log::info!("repaired chart {id}");
+    }
+}
+
+/// e.g. the third triplet of a beat is `{"subdivision": "triplet", "step": 2}`
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy)]
+pub(super) struct OffBeat {
+    subdivision: Subdivision,
+    step: usize,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(super) enum ChartEventKind {
+    Spawn {
+        enemy: EnemyKind,
+        at: SpawnPoint,
+    },
+    /// enemies advance once every `step_every` beats
+    Tempo {
+        step_every: usize,
+    },
+    /// scales how far enemies advance on each step
+    Intensity {
+        level: f32,
+    },
+}
+
+const LANE_COUNT: usize = 8;
+pub(super) const LANE_SPAWN_RADIUS: f32 = 450.0;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+#[serde(untagged)]
+pub(super) enum SpawnPoint {
+    /// one of the evenly spaced directions around the flag, counter-clockwise from the right
+    Lane {
+        lane: usize,
+    },
+    Position {
+        x: f32,
+        y: f32,
+    },
+}
+
+impl SpawnPoint {
+    pub(super) fn position(self) -> Vec2 {
+        match self {
+            SpawnPoint::Lane { lane } => {
+                let angle = (lane % LANE_COUNT) as f32 * std::f32::consts::TAU / LANE_COUNT as f32;
+                Vec2::from_angle(angle) * LANE_SPAWN_RADIUS
+            }
+            SpawnPoint::Position { x, y } => Vec2::new(x, y),
+        }
+    }
+}
+
+/// how enemies currently advance, as set by the chart
+#[derive(Resource)]
+pub(super) struct EnemyPacing {
+    pub(super) step_every: usize,
+    pub(super) intensity: f32,
+}
+
+impl Default for EnemyPacing {
+    fn default() -> Self {
+        Self {
+            step_every: 1,
+            intensity: 1.0,
+        }
+    }
+}
+
+/// the index of the next chart event to play
+#[derive(Resource, Default)]
+pub(super) struct ChartPlayback {
+    next_event: usize,
+}
+
+pub(super) fn reset_chart_playback(
+    mut playback: ResMut<ChartPlayback>,
+    mut pacing: ResMut<EnemyPacing>,
+) {
+    *playback = ChartPlayback::default();
+    *pacing = EnemyPacing::default();
+}
+
+pub(super) fn play_chart(
+    beat_index: Res<BeatIndex>,
+    position: Res<MusicalPosition>,
+    song: Res<CurrentSong>,
+    charts: Res<Assets<Chart>>,
+    mut playback: ResMut<ChartPlayback>,
+    mut pacing: ResMut<EnemyPacing>,
+    mut commands: Commands,
+    asset_handles: Res<StartupAssetHandles>,
+    atlases: Res<Assets<SpriteAtlas>>,
+) {
+    let Some(chart) = song.chart.as_ref().and_then(|chart| charts.get(chart)) else {
+        return;
+    };
+
+    let atlas = atlases.get(&asset_handles.sprite_atlas).unwrap();
+    let beat_position = beat_index.0 as f32 + position.fraction;
+
+    // play everything up to now, in case beats were skipped
+    while let Some(event) = chart.events.get(playback.next_event)
+        && event.beat_position() <= beat_position
+    {
+        playback.next_event += 1;
+
+        match event.kind {
+            ChartEventKind::Spawn { enemy, at } => {
+                spawn_enemy(&mut commands, &asset_handles, atlas, enemy, at.position())
+            }
+            ChartEventKind::Tempo { step_every } => {
+                pacing.step_every = step_every.max(1);
+            }
+            ChartEventKind::Intensity { level } => {
+                pacing.intensity = level;
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Judging scribbles, turning them into hit circles, and applying [`Damage`]
+
+use bevy::prelude::*;
+use inline_tweak::tweak_fn;
+
+use crate::beats::Beats;
+use crate::health::Health;
+use crate::input::Intent;
+use crate::quill::{QuillReticle, SCRIBBLE_HORIZONTAL_RANGE};
+use crate::settings::Settings;
+use crate::songs::CurrentSong;
+use crate::timing::TrackTimer;
+use crate::{CombatSet, Screen};
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<TimingWindows>()
+        .add_message::<ScribbleJudged>()
+        .add_observer(apply_damage)
+        .add_systems(
+            Update,
+            (
+                judge_scribbles.in_set(CombatSet::Judge),
+                (remove_hit_circles, remove_got_hit, add_player_hit_circle)
+                    .chain()
+                    .in_set(CombatSet::Attack),
+            ),
+        );
+}
+
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub(super) struct HitCircle {
+    pub(super) radius: f32,
+}
+
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub(super) struct GotHit;
+
+/// Damage dealt to anything with [`Health`]
+///
+/// Health, health bars, the score and anything else reacting to a hit should
+/// observe this, rather than checking health themselves.
+#[derive(EntityEvent)]
+pub(super) struct Damage {
+    #[event_target]
+    pub(super) target: Entity,
+    pub(super) amount: i32,
+    /// what dealt the damage: a hit circle, or an enemy reaching the flag
+    pub(super) source: Entity,
+    /// the timing of the scribble that dealt the damage, if it came from the player
+    pub(super) judgement: Option<Judgement>,
+}
+
+/// Sent once when an entity's [`Health`] runs out
+#[derive(EntityEvent)]
+pub(super) struct Died {
+    pub(super) entity: Entity,
+}
+
+fn apply_damage(damage: On<Damage>, mut commands: Commands, mut healths: Query<&mut Health>) {
+    let Ok(mut health) = healths.get_mut(damage.target) else {
+        return;
+    };
+
+    let was_alive = health.remaining > 0;
+    health.remaining -= damage.amount;
+
+    if was_alive && health.remaining <= 0 {
+        commands.trigger(Died {
+            entity: damage.target,
+        });
+    }
+}
+
+#[tweak_fn]
+fn remove_hit_circles(mut commands: Commands, hit_circles: Query<Entity, With<HitCircle>>) {
+    for hit_circle in &hit_circles {
+        commands.entity(hit_circle).despawn();
+    }
+}
+
+#[tweak_fn]
+fn remove_got_hit(mut commands: Commands, got_hits: Query<Entity, With<GotHit>>) {
+    for ent in &got_hits {
+        commands.entity(ent).remove::<GotHit>();
+    }
+}
+
+#[tweak_fn]
+fn add_player_hit_circle(
+    mut commands: Commands,
+    intent: Res<Intent>,
+    quills: Query<&Transform, With<QuillReticle>>,
+) {
+    if !intent.quill_down {
+        return;
+    }
+
+    let Ok(quill_transform) = quills.single().cloned() else {
+        return;
+    };
+
+    commands.spawn((
+        HitCircle {
+            radius: SCRIBBLE_HORIZONTAL_RANGE,
+        },
+        DespawnOnExit(Screen::InGame),
+        quill_transform,
+    ));
+}
+
+/// how close a scribble landed to the nearest beat
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Judgement {
+    Perfect,
+    Great,
+    Good,
+    Miss,
+}
+
+impl Judgement {
+    /// how much this judgement counts towards accuracy, 0.0-1.0
+    pub(super) fn accuracy(self) -> f32 {
+        match self {
+            Judgement::Perfect => 1.0,
+            Judgement::Great => 0.75,
+            Judgement::Good => 0.5,
+            Judgement::Miss => 0.0,
+        }
+    }
+
+    /// the points scored for each enemy under the quill, before the multiplier
+    pub(super) fn points(self) -> u32 {
+        match self {
+            Judgement::Perfect => 300,
+            Judgement::Great => 200,
+            Judgement::Good => 100,
+            Judgement::Miss => 0,
+        }
+    }
+
+    /// the damage dealt to each enemy under the quill
+    pub(super) fn damage(self) -> i32 {
+        match self {
+            Judgement::Perfect => 2,
+            Judgement::Great | Judgement::Good => 1,
+            Judgement::Miss => 0,
+        }
+    }
+}
+
+/// The widest offset from a beat (in seconds, early or late) for each judgement
+#[derive(Resource)]
+pub(super) struct TimingWindows {
+    perfect: f32,
+    great: f32,
+    good: f32,
+}
+
+impl Default for TimingWindows {
+    fn default() -> Self {
+        Self {
+            perfect: 0.045,
+            great: 0.09,
+            good: 0.15,
+        }
+    }
+}
+
+impl TimingWindows {
+    fn judge(&self, offset: f32) -> Judgement {
+        let offset = offset.abs();
+        if offset <= self.perfect {
+            Judgement::Perfect
+        } else if offset <= self.great {
+            Judgement::Great
+        } else if offset <= self.good {
+            Judgement::Good
+        } else {
+            Judgement::Miss
+        }
+    }
+}
+
+/// A scribble started by the player, judged against the nearest beat
+#[derive(Message, Debug, Clone, Copy)]
+pub(super) struct ScribbleJudged {
+    pub(super) judgement: Judgement,
+}
+
+pub(super) fn judge_scribbles(
+    intent: Res<Intent>,
+    track_timer: Res<TrackTimer>,
+    song: Res<CurrentSong>,
+    beats_assets: Res<Assets<Beats>>,
+    timing_windows: Res<TimingWindows>,
+    settings: Res<Settings>,
+    mut judged_scribbles: MessageWriter<ScribbleJudged>,
+) {
+    if !intent.quill_pressed {
+        return;
+    }
+
+    let beats = beats_assets.get(&song.beats).unwrap();
+    let press_time = intent
+        .press_time
+        .unwrap_or_else(|| track_timer.0.elapsed_secs());
+    // the player reacts to what they heard, and their reaction arrives late
+    let track_time = press_time - settings.audio_offset - settings.input_offset;
+    let Some(offset) = beats.nearest_beat_offset(track_time) else {
+        return;
+    };
+
+    let judgement = timing_windows.judge(offset);
+    log::debug!("{judgement:?} ({offset:+.3}s)");
+    judged_scribbles.write(ScribbleJudged { judgement });
+}
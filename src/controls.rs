@@ -0,0 +1,159 @@
+//! The controls screen, for rebinding inputs
+
+use bevy::prelude::*;
+
+use crate::Screen;
+use crate::input::{Action, ActionInput, Binding, Bindings, PAUSE_KEY};
+use crate::settings::Settings;
+use crate::ui::{menu_button, return_to_menu};
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<Rebinding>()
+        .add_systems(OnEnter(Screen::Controls), spawn_controls_menu)
+        .add_systems(
+            Update,
+            (capture_rebind, update_binding_labels)
+                .chain()
+                .run_if(in_state(Screen::Controls)),
+        );
+}
+
+/// the action waiting for the player to press its new input
+#[derive(Resource, Default)]
+struct Rebinding(Option<Action>);
+
+#[derive(Component)]
+struct BindingLabel(Action);
+
+/// the instructions above the bindings, which also say why an input wasn't bound
+#[derive(Component)]
+struct RebindHint;
+
+const REBIND_HINT: &str = "click an action, then press an input to add to it";
+
+fn spawn_controls_menu(mut commands: Commands, mut rebinding: ResMut<Rebinding>) {
+    rebinding.0 = None;
+
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(16.0),
+            ..default()
+        },
+        GlobalZIndex(2),
+        DespawnOnExit(Screen::Controls),
+        Pickable::IGNORE,
+        Children::spawn(SpawnWith(|parent: &mut ChildSpawner| {
+            parent.spawn((
+                RebindHint,
+                Text::new(REBIND_HINT),
+                TextFont {
+                    font_size: 24.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.15, 0.15, 0.15)),
+            ));
+
+            for action in Action::ALL {
+                parent
+                    .spawn(menu_button(""))
+                    .insert(BindingLabel(action))
+                    .observe(
+                        move |_on_click: On<Pointer<Click>>, mut rebinding: ResMut<Rebinding>| {
+                            rebinding.0 = Some(action);
+                        },
+                    );
+            }
+
+            parent.spawn(menu_button("Reset")).observe(
+                |_on_click: On<Pointer<Click>>, mut settings: ResMut<Settings>| {
+                    settings.bindings = Bindings::default();
+                    settings.save();
+                },
+            );
+            parent.spawn(menu_button("Back")).observe(return_to_menu);
+        })),
+    ));
+}
+
+fn capture_rebind(
+    input: ActionInput,
+    buttons: Query<&Interaction, With<Button>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut settings: ResMut<Settings>,
+    mut hints: Query<&mut Text, With<RebindHint>>,
+) {
+    let Some(action) = rebinding.0 else {
+        return;
+    };
+
+    let mut set_hint = |hint: String| {
+        for mut text in &mut hints {
+            text.0.clone_from(&hint);
+        }
+    };
+
+    // the pause key always pauses, so it backs out here rather than being bound
+    if input.keyboard.just_pressed(PAUSE_KEY) {
+        rebinding.0 = None;
+        set_hint(REBIND_HINT.to_string());
+        return;
+    }
+
+    let Some(binding) = input.any_just_pressed() else {
+        return;
+    };
+
+    // clicks on the menu's own buttons are for the buttons, including the one that started this
+    let over_button = buttons
+        .iter()
+        .any(|interaction| *interaction != Interaction::None);
+    if matches!(binding, Binding::Mouse(_)) && over_button {
+        return;
+    }
+
+    if let Some(bound_to) = settings.bindings.action_for(binding)
+        && bound_to != action
+    {
+        set_hint(format!(
+            "{binding} is already bound to {}, pick another input",
+            bound_to.label()
+        ));
+        return;
+    }
+
+    rebinding.0 = None;
+    set_hint(REBIND_HINT.to_string());
+    settings.bindings.bind(action, binding);
+    settings.save();
+}
+
+fn update_binding_labels(
+    settings: Res<Settings>,
+    rebinding: Res<Rebinding>,
+    labels: Query<(&BindingLabel, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    for (label, children) in &labels {
+        let bound = if rebinding.0 == Some(label.0) {
+            "press an input (escape to cancel)".to_string()
+        } else {
+            let bindings = settings.bindings.get(label.0);
+            bindings
+                .iter()
+                .map(|binding| binding.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        for &child in children {
+            if let Ok(mut text) = texts.get_mut(child) {
+                text.0 = format!("{}: {bound}", label.0.label());
+            }
+        }
+    }
+}
@@ -0,0 +1,642 @@
+//! A dev-only chart editor
+//!
+//! Plays the selected song with a scrubbable timeline built from its beats.
+//!
+//! - space: play/pause
+//! - left/right: previous/next beat
+//! - click the timeline: jump to the nearest beat
+//! - left click: place a spawn event on the current beat, or drag an existing one
+//! - right click: delete a spawn event
+//! - [ and ]: nudge the current beat earlier or later
+//! - d: toggle a downbeat marker on the current beat
+//! - ctrl+s: save the chart and beats, listing new charts in the song library (native only)
+//! - escape: back to the menu
+
+use std::collections::HashSet;
+
+use bevy::asset::AssetPath;
+use bevy::color::palettes::tailwind;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_kira_audio::{Audio, AudioControl, AudioInstance, AudioTween};
+
+use crate::Screen;
+use crate::assets::StartupAssetHandles;
+use crate::beats::Beats;
+use crate::chart::{Chart, ChartEvent, ChartEventKind, LANE_SPAWN_RADIUS, SpawnPoint};
+use crate::enemies::EnemyKind;
+use crate::input::read_mouse_pos_in_world_space;
+use crate::songs::{CurrentSong, SongDestination, SongLibrary};
+use crate::timing::{TrackInstance, stop_track};
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<EditorState>()
+        .add_systems(
+            OnEnter(Screen::Editor),
+            (start_editor, spawn_editor_ui).chain(),
+        )
+        .add_systems(OnExit(Screen::Editor), stop_track)
+        .add_systems(
+            Update,
+            (
+                editor_keyboard,
+                follow_playback,
+                click_timeline,
+                edit_spawn_events,
+                draw_spawn_events,
+                update_timeline,
+                update_editor_text,
+            )
+                .chain()
+                .run_if(in_state(Screen::Editor)),
+        );
+}
+
+/// seconds each press of [ or ] moves a beat
+const BEAT_NUDGE: f32 = 0.01;
+/// height of the timeline, in logical pixels
+const TIMELINE_HEIGHT: f32 = 48.0;
+/// how close (in world units) the mouse must be to grab a spawn event
+const GRAB_RADIUS: f32 = 24.0;
+/// how many upcoming beats of spawn events to preview
+const PREVIEW_BEATS: usize = 3;
+
+#[derive(Resource, Default)]
+struct EditorState {
+    /// the selected beat index
+    beat: usize,
+    playing: bool,
+    /// the index of the chart event being dragged
+    dragging: Option<usize>,
+}
+
+pub(super) fn start_editor_song_select(
+    _on_click: On<Pointer<Click>>,
+    mut destination: ResMut<SongDestination>,
+    mut next_screen: ResMut<NextState<Screen>>,
+) {
+    destination.0 = Screen::Editor;
+    next_screen.set(Screen::SongSelect)
+}
+
+fn start_editor(
+    mut commands: Commands,
+    audio: Res<Audio>,
+    mut song: ResMut<CurrentSong>,
+    mut charts: ResMut<Assets<Chart>>,
+    mut state: ResMut<EditorState>,
+) {
+    let instance = audio.play(song.audio.clone()).paused().handle();
+    commands.insert_resource(TrackInstance::new(instance));
+
+    // start a fresh chart for songs without one
+    let has_chart = song
+        .chart
+        .as_ref()
+        .is_some_and(|chart| charts.contains(chart));
+    if !has_chart {
+        song.chart = Some(charts.add(Chart { events: Vec::new() }));
+    }
+
+    *state = EditorState::default();
+}
+
+#[derive(Component)]
+struct EditorText;
+
+#[derive(Component)]
+struct TimelineTick(usize);
+
+#[derive(Component)]
+struct TimelinePlayhead;
+
+fn spawn_editor_ui(
+    mut commands: Commands,
+    song: Res<CurrentSong>,
+    beats_assets: Res<Assets<Beats>>,
+) {
+    let beats = beats_assets.get(&song.beats).unwrap();
+
+    commands.spawn((
+        EditorText,
+        Text::new(""),
+        TextFont {
+            font_size: 20.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.15, 0.15, 0.15)),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(16.0),
+            left: Val::Px(16.0),
+            ..default()
+        },
+        DespawnOnExit(Screen::Editor),
+    ));
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(0.0),
+                width: Val::Percent(100.0),
+                height: Val::Px(TIMELINE_HEIGHT),
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+            DespawnOnExit(Screen::Editor),
+        ))
+        .with_children(|parent| {
+            for beat in 0..beats.beats.len() {
+                parent.spawn((
+                    TimelineTick(beat),
+                    Node {
+                        position_type: PositionType::Absolute,
+                        width: Val::Px(1.0),
+                        height: Val::Percent(50.0),
+                        bottom: Val::Px(0.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.6, 0.6, 0.6)),
+                ));
+            }
+
+            parent.spawn((
+                TimelinePlayhead,
+                Node {
+                    position_type: PositionType::Absolute,
+                    width: Val::Px(3.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                BackgroundColor(Color::Srgba(tailwind::SKY_400)),
+            ));
+        });
+}
+
+fn seek_to_beat(instance: Option<&mut AudioInstance>, beats: &Beats, beat: usize) {
+    let Some(instance) = instance else {
+        return;
+    };
+
+    instance.pause(AudioTween::default());
+    if let Some(&beat_time) = beats.beats.get(beat) {
+        instance.seek_to(beat_time as f64);
+    }
+}
+
+fn editor_keyboard(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    song: Res<CurrentSong>,
+    track_instance: Res<TrackInstance>,
+    charts: Res<Assets<Chart>>,
+    mut beats_assets: ResMut<Assets<Beats>>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    mut state: ResMut<EditorState>,
+    mut next_screen: ResMut<NextState<Screen>>,
+    asset_handles: Res<StartupAssetHandles>,
+    mut libraries: ResMut<Assets<SongLibrary>>,
+) {
+    if keyboard.just_pressed(KeyCode::Escape) {
+        next_screen.set(Screen::Menu);
+        return;
+    }
+
+    let ctrl = keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if ctrl && keyboard.just_pressed(KeyCode::KeyS) {
+        save_song(&song, &charts, &beats_assets);
+        if let Some(library) = libraries.get_mut(&asset_handles.song_library) {
+            add_saved_chart_to_library(&song, &asset_handles.song_library, library);
+        }
+        return;
+    }
+
+    let mut instance = audio_instances.get_mut(&track_instance.handle);
+    let beats = beats_assets.get(&song.beats).unwrap();
+    let last_beat = beats.beats.len().saturating_sub(1);
+
+    if keyboard.just_pressed(KeyCode::Space) {
+        state.playing = !state.playing;
+        if state.playing
+            && let Some(instance) = instance.as_deref_mut()
+        {
+            if let Some(&beat_time) = beats.beats.get(state.beat) {
+                instance.seek_to(beat_time as f64);
+            }
+            instance.resume(AudioTween::default());
+        } else {
+            seek_to_beat(instance.as_deref_mut(), beats, state.beat);
+        }
+    }
+
+    let step_back = keyboard.just_pressed(KeyCode::ArrowLeft);
+    let step_forward = keyboard.just_pressed(KeyCode::ArrowRight);
+    if step_back || step_forward {
+        state.playing = false;
+        state.beat = if step_back {
+            state.beat.saturating_sub(1)
+        } else {
+            (state.beat + 1).min(last_beat)
+        };
+        seek_to_beat(instance.as_deref_mut(), beats, state.beat);
+    }
+
+    let nudge_earlier = keyboard.just_pressed(KeyCode::BracketLeft);
+    let nudge_later = keyboard.just_pressed(KeyCode::BracketRight);
+    if (nudge_earlier || nudge_later)
+        && let Some(&beat_time) = beats.beats.get(state.beat)
+    {
+        let beat = state.beat;
+        let nudge = if nudge_earlier {
+            -BEAT_NUDGE
+        } else {
+            BEAT_NUDGE
+        };
+
+        // keep beats in order
+        let min = beat
+            .checked_sub(1)
+            .and_then(|prev| beats.beats.get(prev))
+            .map_or(0.0, |prev| prev + BEAT_NUDGE);
+        let max = beats
+            .beats
+            .get(beat + 1)
+            .map_or(f32::MAX, |next| next - BEAT_NUDGE);
+        let nudged = (beat_time + nudge).clamp(min, max);
+
+        // only borrow mutably on an edit, since that sends a Modified event and revalidates the beats
+        let beats = beats_assets.get_mut(&song.beats).unwrap();
+        beats.beats[beat] = nudged;
+
+        state.playing = false;
+        seek_to_beat(instance, beats, beat);
+    }
+
+    if keyboard.just_pressed(KeyCode::KeyD) {
+        let beats = beats_assets.get_mut(&song.beats).unwrap();
+        match beats.downbeats.binary_search(&state.beat) {
+            Ok(i) => {
+                beats.downbeats.remove(i);
+            }
+            Err(i) => beats.downbeats.insert(i, state.beat),
+        }
+    }
+}
+
+/// keeps the selected beat under the playhead while playing
+fn follow_playback(
+    song: Res<CurrentSong>,
+    beats_assets: Res<Assets<Beats>>,
+    track_instance: Res<TrackInstance>,
+    audio_instances: Res<Assets<AudioInstance>>,
+    mut state: ResMut<EditorState>,
+) {
+    if !state.playing {
+        return;
+    }
+
+    let Some(position) = audio_instances
+        .get(&track_instance.handle)
+        .and_then(|instance| instance.state().position())
+    else {
+        return;
+    };
+
+    let beats = beats_assets.get(&song.beats).unwrap();
+    let position = position as f32;
+    state.beat = beats
+        .beats
+        .partition_point(|&beat| beat <= position)
+        .saturating_sub(1);
+}
+
+fn click_timeline(
+    window: Single<&Window, With<PrimaryWindow>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    song: Res<CurrentSong>,
+    beats_assets: Res<Assets<Beats>>,
+    track_instance: Res<TrackInstance>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    mut state: ResMut<EditorState>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+
+    if cursor.y < window.height() - TIMELINE_HEIGHT {
+        return;
+    }
+
+    let beats = beats_assets.get(&song.beats).unwrap();
+    let Some(&track_end) = beats.beats.last() else {
+        return;
+    };
+
+    let clicked_time = cursor.x / window.width() * track_end;
+    let Some(offset) = beats.nearest_beat_offset(clicked_time) else {
+        return;
+    };
+
+    let nearest_time = clicked_time - offset;
+    state.beat = beats.beats.partition_point(|&beat| beat < nearest_time);
+    state.playing = false;
+    seek_to_beat(
+        audio_instances.get_mut(&track_instance.handle),
+        beats,
+        state.beat,
+    );
+}
+
+fn spawn_event_at(chart: &Chart, beat: usize, pos: Vec2) -> Option<usize> {
+    chart.events.iter().position(|event| match event.kind {
+        ChartEventKind::Spawn { at, .. } => {
+            event.beat == beat && at.position().distance(pos) < GRAB_RADIUS
+        }
+        _ => false,
+    })
+}
+
+fn edit_spawn_events(
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera_query: Single<(&Camera, &GlobalTransform)>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    song: Res<CurrentSong>,
+    mut charts: ResMut<Assets<Chart>>,
+    mut state: ResMut<EditorState>,
+) {
+    if mouse.just_released(MouseButton::Left) {
+        state.dragging = None;
+    }
+
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+
+    // the timeline handles its own clicks
+    if cursor.y >= window.height() - TIMELINE_HEIGHT && state.dragging.is_none() {
+        return;
+    }
+
+    let (camera, camera_transform) = *camera_query;
+    let Some(mouse_pos) = read_mouse_pos_in_world_space(&window, camera, camera_transform) else {
+        return;
+    };
+
+    let chart_handle = song.chart.as_ref().unwrap();
+    let beat = state.beat;
+    let grabbed = spawn_event_at(charts.get(chart_handle).unwrap(), beat, mouse_pos);
+
+    // only borrow the chart mutably on an edit, since that sends a Modified event and revalidates it
+    if mouse.just_pressed(MouseButton::Right)
+        && let Some(index) = grabbed
+    {
+        charts.get_mut(chart_handle).unwrap().events.remove(index);
+        state.dragging = None;
+        return;
+    }
+
+    if mouse.just_pressed(MouseButton::Left) {
+        let chart = charts.get_mut(chart_handle).unwrap();
+        let index = grabbed.unwrap_or_else(|| {
+            let index = chart
+                .events
+                .partition_point(|event| event.beat_position() <= beat as f32);
+            let event = ChartEvent {
+                beat,
+                off_beat: None,
+                kind: ChartEventKind::Spawn {
+                    enemy: EnemyKind::Soldier,
+                    at: SpawnPoint::Position {
+                        x: mouse_pos.x,
+                        y: mouse_pos.y,
+                    },
+                },
+            };
+            chart.events.insert(index, event);
+            index
+        });
+
+        state.dragging = Some(index);
+    }
+
+    if mouse.pressed(MouseButton::Left)
+        && let Some(index) = state.dragging
+        && let Some(chart) = charts.get_mut(chart_handle)
+        && let Some(ChartEventKind::Spawn { at, .. }) =
+            chart.events.get_mut(index).map(|event| &mut event.kind)
+    {
+        *at = SpawnPoint::Position {
+            x: mouse_pos.x,
+            y: mouse_pos.y,
+        };
+    }
+}
+
+fn draw_spawn_events(
+    mut gizmos: Gizmos,
+    song: Res<CurrentSong>,
+    charts: Res<Assets<Chart>>,
+    state: Res<EditorState>,
+) {
+    let guide_color = Color::srgba(0.15, 0.15, 0.15, 0.2);
+    gizmos.circle_2d(Vec2::ZERO, LANE_SPAWN_RADIUS, guide_color);
+    gizmos.cross_2d(Vec2::ZERO, 16.0, guide_color);
+
+    let Some(chart) = song.chart.as_ref().and_then(|chart| charts.get(chart)) else {
+        return;
+    };
+
+    let preview = state.beat..=state.beat + PREVIEW_BEATS;
+    for event in &chart.events {
+        let ChartEventKind::Spawn { at, .. } = event.kind else {
+            continue;
+        };
+
+        if !preview.contains(&event.beat) {
+            continue;
+        }
+
+        let beats_ahead = event.beat - state.beat;
+        let alpha = 1.0 - beats_ahead as f32 / (PREVIEW_BEATS + 1) as f32;
+        let color = Color::Srgba(tailwind::RED_500.with_alpha(alpha));
+        gizmos.circle_2d(at.position(), GRAB_RADIUS, color);
+    }
+}
+
+fn update_timeline(
+    song: Res<CurrentSong>,
+    beats_assets: Res<Assets<Beats>>,
+    charts: Res<Assets<Chart>>,
+    track_instance: Res<TrackInstance>,
+    audio_instances: Res<Assets<AudioInstance>>,
+    state: Res<EditorState>,
+    mut ticks: Query<(&TimelineTick, &mut Node, &mut BackgroundColor), Without<TimelinePlayhead>>,
+    mut playheads: Query<&mut Node, With<TimelinePlayhead>>,
+) {
+    let beats = beats_assets.get(&song.beats).unwrap();
+    let Some(&track_end) = beats.beats.last() else {
+        return;
+    };
+
+    let charted_beats: HashSet<usize> = song
+        .chart
+        .as_ref()
+        .and_then(|chart| charts.get(chart))
+        .map(|chart| chart.events.iter().map(|event| event.beat).collect())
+        .unwrap_or_default();
+
+    for (tick, mut node, mut color) in &mut ticks {
+        let beat_time = beats.beats.get(tick.0).copied().unwrap_or(track_end);
+        node.left = Val::Percent(beat_time / track_end * 100.0);
+        color.0 = if tick.0 == state.beat {
+            Color::Srgba(tailwind::SKY_400)
+        } else if charted_beats.contains(&tick.0) {
+            Color::Srgba(tailwind::RED_400)
+        } else if beats.bar_position(tick.0).1 == 0 {
+            Color::srgb(0.3, 0.3, 0.3)
+        } else {
+            Color::srgb(0.6, 0.6, 0.6)
+        };
+    }
+
+    let playhead_time = if state.playing {
+        audio_instances
+            .get(&track_instance.handle)
+            .and_then(|instance| instance.state().position())
+            .map(|position| position as f32)
+    } else {
+        None
+    };
+    let playhead_time = playhead_time
+        .or_else(|| beats.beats.get(state.beat).copied())
+        .unwrap_or_default();
+
+    for mut node in &mut playheads {
+        node.left = Val::Percent(playhead_time / track_end * 100.0);
+    }
+}
+
+fn update_editor_text(
+    song: Res<CurrentSong>,
+    beats_assets: Res<Assets<Beats>>,
+    charts: Res<Assets<Chart>>,
+    state: Res<EditorState>,
+    mut texts: Query<&mut Text, With<EditorText>>,
+) {
+    let beats = beats_assets.get(&song.beats).unwrap();
+    let beat_time = beats.beats.get(state.beat).copied().unwrap_or_default();
+    let (bar, beat_in_bar) = beats.bar_position(state.beat);
+    let events = song
+        .chart
+        .as_ref()
+        .and_then(|chart| charts.get(chart))
+        .map_or(0, |chart| {
+            chart.events.iter().filter(|e| e.beat == state.beat).count()
+        });
+
+    for mut text in &mut texts {
+        text.0 = format!(
+            "beat {} / {}  ({beat_time:.3}s)  bar {bar} beat {}\n\
+             events on beat: {events}\n\
+             space: play/pause  left/right: step  [ ]: nudge beat  d: downbeat\n\
+             click: place/drag  right click: delete  ctrl+s: save",
+            state.beat,
+            beats.beats.len(),
+            beat_in_bar + 1,
+        );
+    }
+}
+
+fn save_song(song: &CurrentSong, charts: &Assets<Chart>, beats_assets: &Assets<Beats>) {
+    let Some(beats_path) = song.beats.path() else {
+        log::warn!("beats have no asset path; not saved");
+        return;
+    };
+
+    if let Some(beats) = beats_assets.get(&song.beats) {
+        save_json(beats_path, beats);
+    }
+
+    let Some(chart_handle) = song.chart.as_ref() else {
+        return;
+    };
+
+    let Some(chart) = charts.get(chart_handle) else {
+        return;
+    };
+
+    let chart_path = match chart_handle.path() {
+        Some(chart_path) => chart_path.clone(),
+        None => AssetPath::from(new_chart_path(beats_path)),
+    };
+
+    save_json(&chart_path, chart);
+}
+
+/// charts created in the editor are saved next to the beats
+fn new_chart_path(beats_path: &AssetPath) -> String {
+    let beats_path = beats_path.path().to_string_lossy();
+    beats_path.replace("beats.json", "chart.json")
+}
+
+/// Lists a chart created in the editor in the song library, so the game loads it
+///
+/// Charts that were loaded from a file are already listed.
+fn add_saved_chart_to_library(
+    song: &CurrentSong,
+    library_handle: &Handle<SongLibrary>,
+    library: &mut SongLibrary,
+) {
+    let (Some(beats_path), Some(chart_handle)) = (song.beats.path(), song.chart.as_ref()) else {
+        return;
+    };
+    if chart_handle.path().is_some() {
+        return;
+    }
+    let Some(library_path) = library_handle.path() else {
+        return;
+    };
+
+    let chart_path = new_chart_path(beats_path);
+    let beats_path = beats_path.path().to_string_lossy();
+    let Some(entry) = library
+        .songs
+        .iter_mut()
+        .find(|entry| entry.beats == beats_path)
+    else {
+        log::warn!("{beats_path} isn't in the song library; the chart won't be listed");
+        return;
+    };
+
+    entry.chart = Some(chart_path);
+    save_json(library_path, library);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_json(asset_path: &AssetPath, value: &impl serde::Serialize) {
+    let path = std::path::Path::new("assets").join(asset_path.path());
+
+    // match the indentation of the existing assets
+    let mut json = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut json, formatter);
+    if let Err(e) = value.serialize(&mut serializer) {
+        log::warn!("failed to serialize {}: {e}", path.display());
+        return;
+    }
+    json.push(b'\n');
+
+    match std::fs::write(&path, json) {
+        Ok(()) => log::info!("saved {}", path.display()),
+        Err(e) => log::warn!("failed to write {}: {e}", path.display()),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn save_json(asset_path: &AssetPath, _value: &impl serde::Serialize) {
+    log::warn!("saving {asset_path} is only supported on native builds");
+}
@@ -0,0 +1,476 @@
+//! Spawning enemies, moving them towards the flag, and their deaths
+
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, TAU};
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use inline_tweak::tweak_fn;
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use crate::assets::{SpriteAtlas, StartupAssetHandles};
+use crate::chart::{Chart, ChartPlayback, EnemyPacing, play_chart, reset_chart_playback};
+use crate::combat::{Damage, Died, GotHit, HitCircle, ScribbleJudged};
+use crate::health::{Health, Healthbar, HealthbarFill, HealthbarTrail};
+use crate::lerp::{BeatLerp, LerpDestination, LerpDestinationOf, LerpSet};
+use crate::quill::make_ink_color;
+use crate::replay::GameRng;
+use crate::songs::CurrentSong;
+use crate::timing::{BeatIndex, MusicalPosition, OnBeat, Subdivision};
+use crate::{CleanupSet, CombatSet, ENEMY_Z, MovementSet, Screen, SpawnSet};
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<ChartPlayback>()
+        .init_resource::<EnemyPacing>()
+        .init_resource::<DeathInk>()
+        .add_systems(OnEnter(Screen::InGame), reset_chart_playback)
+        .add_systems(
+            Update,
+            (
+                add_enemy_hits.in_set(CombatSet::Hit),
+                (play_chart, spawn_random_enemies).chain().in_set(SpawnSet),
+                update_enemy_lerp_dests.in_set(MovementSet).before(LerpSet),
+                animate_dying_enemies.in_set(MovementSet),
+                fade_ink_splats.in_set(CleanupSet),
+            ),
+        )
+        .add_observer(start_dying);
+}
+
+#[derive(Component)]
+pub(super) struct Enemy;
+
+#[tweak_fn]
+pub(super) fn add_enemy_hits(
+    mut commands: Commands,
+    enemies: Query<(Entity, &Transform), With<Enemy>>,
+    mut judged_scribbles: MessageReader<ScribbleJudged>,
+    hit_circles: Query<(Entity, &HitCircle, &Transform), Without<Enemy>>,
+) {
+    // only a freshly started scribble can deal damage
+    let judgement = judged_scribbles
+        .read()
+        .last()
+        .map(|judged| judged.judgement);
+
+    for (enemy, enemy_transform) in &enemies {
+        let enemy_pos = enemy_transform.translation.xy();
+
+        let mut hit_by = None;
+        for (hit_circle_entity, hit_circle, hit_transform) in &hit_circles {
+            let center = hit_transform.translation.xy();
+            let distance = enemy_pos.distance(center);
+            if distance < hit_circle.radius {
+                hit_by = Some(hit_circle_entity);
+                break;
+            }
+        }
+
+        let Some(hit_circle) = hit_by else {
+            continue;
+        };
+
+        commands.entity(enemy).insert(GotHit);
+        if let Some(judgement) = judgement
+            && judgement.damage() > 0
+        {
+            commands.trigger(Damage {
+                target: enemy,
+                amount: judgement.damage(),
+                source: hit_circle,
+                judgement: Some(judgement),
+            });
+        }
+    }
+}
+
+#[derive(Component)]
+struct EnemyLerpDest;
+
+#[tweak_fn]
+fn spawn_random_enemies(
+    on_beat: Res<OnBeat>,
+    position: Res<MusicalPosition>,
+    mut rng: ResMut<GameRng>,
+    song: Res<CurrentSong>,
+    charts: Res<Assets<Chart>>,
+    mut commands: Commands,
+    asset_handles: Res<StartupAssetHandles>,
+    atlases: Res<Assets<SpriteAtlas>>,
+) {
+    // charted songs spawn from play_chart instead
+    if song
+        .chart
+        .as_ref()
+        .is_some_and(|chart| charts.contains(chart))
+    {
+        return;
+    }
+
+    // one per bar, after any pickup
+    if !position.is_downbeat() || position.bar == 0 || !on_beat.0 {
+        return;
+    }
+
+    let enemy_pos = {
+        let rng = &mut rng.spawn;
+
+        let mut x = rng.random_range(250.0..500.0);
+        if rng.random_bool(0.5) {
+            x *= -1.0;
+        }
+
+        let mut y = rng.random_range(250.0..500.0);
+        if rng.random_bool(0.5) {
+            y *= -1.0;
+        }
+
+        Vec2::new(x, y)
+    };
+
+    let atlas = atlases.get(&asset_handles.sprite_atlas).unwrap();
+    spawn_enemy(
+        &mut commands,
+        &asset_handles,
+        atlas,
+        EnemyKind::Soldier,
+        enemy_pos,
+    );
+}
+
+pub(super) fn spawn_enemy(
+    commands: &mut Commands,
+    asset_handles: &StartupAssetHandles,
+    atlas: &SpriteAtlas,
+    kind: EnemyKind,
+    enemy_pos: Vec2,
+) {
+    let offsets = atlas.get_offsets_or_panic(kind.sprite_name());
+    let sprite = Sprite {
+        image: asset_handles.sprite_sheet.clone(),
+        rect: Some(offsets.as_rect()),
+        flip_x: enemy_pos.x > 0.0,
+        ..default()
+    };
+
+    let enemy = commands
+        .spawn((
+            Enemy,
+            DespawnOnExit(Screen::InGame),
+            Anchor::CENTER,
+            sprite,
+            Transform::from_translation(enemy_pos.extend(ENEMY_Z)),
+            BeatLerp::new(Subdivision::Beat, EaseFunction::CubicOut, 0.5),
+            Health::new(kind.health()),
+            Healthbar {
+                offset: Vec3::new(0.0, 48.0, ENEMY_Z + 1.0),
+                ..default()
+            },
+        ))
+        .id();
+
+    commands.spawn((
+        EnemyLerpDest,
+        Transform::from_translation(enemy_pos.extend(ENEMY_Z)),
+        LerpDestinationOf(enemy),
+    ));
+}
+
+/// beats a defeated enemy takes to die, before it's despawned
+const DEATH_BEATS: f32 = 2.0;
+/// how far a defeated enemy is knocked back, away from the flag
+const DEATH_KNOCKBACK: f32 = 40.0;
+/// beats it takes to draw each line crossing out a dying enemy
+const CROSS_OUT_STROKE_BEATS: f32 = 0.25;
+/// bars an ink splat takes to fade away
+const INK_SPLAT_BARS: f32 = 2.0;
+const INK_SPLAT_Z: f32 = 1.0;
+
+/// A defeated enemy playing its death animation
+///
+/// It's no longer an [`Enemy`], so it can't be hit, move or reach the flag.
+#[derive(Component)]
+struct Dying {
+    /// the beat position it was defeated at
+    start: f32,
+    from: Vec2,
+    to: Vec2,
+    /// how far it topples over, in radians
+    tilt: f32,
+}
+
+/// one of the ink lines crossing out a dying enemy
+#[derive(Component)]
+struct CrossOut {
+    /// beats after defeat that this line is drawn
+    beat: f32,
+}
+
+/// An ink stain left where an enemy was defeated
+#[derive(Component)]
+struct InkSplat {
+    /// the beat position it was left at
+    start: f32,
+    /// its own, so it can fade independently of other splats
+    material: Handle<ColorMaterial>,
+}
+
+/// the meshes (and cross-out material) shared by every death animation
+#[derive(Resource)]
+struct DeathInk {
+    stroke: Handle<Mesh>,
+    /// a unit circle, scaled for each blob of a splat
+    dot: Handle<Mesh>,
+    cross_out: Handle<ColorMaterial>,
+}
+
+impl FromWorld for DeathInk {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let stroke = meshes.add(Capsule2d::new(3.0, 40.0));
+        let dot = meshes.add(Circle::new(1.0));
+        let cross_out = world
+            .resource_mut::<Assets<ColorMaterial>>()
+            .add(make_ink_color());
+
+        Self {
+            stroke,
+            dot,
+            cross_out,
+        }
+    }
+}
+
+fn start_dying(
+    died: On<Died>,
+    mut commands: Commands,
+    enemies: Query<(&Transform, &Sprite, Option<&Children>), With<Enemy>>,
+    healthbar_parts: Query<(), Or<(With<HealthbarFill>, With<HealthbarTrail>)>>,
+    beat_index: Res<BeatIndex>,
+    position: Res<MusicalPosition>,
+    mut rng: ResMut<GameRng>,
+    death_ink: Res<DeathInk>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let Ok((transform, sprite, children)) = enemies.get(died.entity) else {
+        return;
+    };
+
+    // an empty health bar has nothing left to say
+    for &child in children.into_iter().flatten() {
+        if healthbar_parts.contains(child) {
+            commands.entity(child).despawn();
+        }
+    }
+
+    let start = beat_index.0 as f32 + position.fraction;
+    let from = transform.translation.xy();
+    let away_from_flag = from.try_normalize().unwrap_or(Vec2::Y);
+    let tilt = if sprite.flip_x { -FRAC_PI_2 } else { FRAC_PI_2 };
+
+    let cross_out = |beat: f32, angle: f32| {
+        (
+            CrossOut { beat },
+            Mesh2d(death_ink.stroke.clone()),
+            MeshMaterial2d(death_ink.cross_out.clone()),
+            Transform {
+                translation: Vec3::Z,
+                rotation: Quat::from_rotation_z(angle),
+                scale: Vec3::new(1.0, 0.0, 1.0),
+            },
+            Visibility::Hidden,
+        )
+    };
+
+    commands
+        .entity(died.entity)
+        .remove::<(Enemy, BeatLerp, Healthbar)>()
+        .insert(Dying {
+            start,
+            from,
+            to: from + away_from_flag * DEATH_KNOCKBACK,
+            tilt,
+        })
+        .with_children(|enemy| {
+            // one line on the beat of defeat, the other on the next beat
+            enemy.spawn(cross_out(0.0, FRAC_PI_4));
+            enemy.spawn(cross_out(1.0, -FRAC_PI_4));
+        });
+
+    spawn_ink_splat(
+        &mut commands,
+        &death_ink,
+        &mut materials,
+        &mut rng.visual,
+        from,
+        start,
+    );
+}
+
+fn spawn_ink_splat(
+    commands: &mut Commands,
+    death_ink: &DeathInk,
+    materials: &mut Assets<ColorMaterial>,
+    rng: &mut StdRng,
+    pos: Vec2,
+    start: f32,
+) {
+    let material = materials.add(make_ink_color());
+
+    let blob = |offset: Vec2, radius: f32| {
+        (
+            Mesh2d(death_ink.dot.clone()),
+            MeshMaterial2d(material.clone()),
+            Transform::from_translation(offset.extend(0.0)).with_scale(Vec3::splat(radius)),
+        )
+    };
+
+    commands
+        .spawn((
+            InkSplat {
+                start,
+                material: material.clone(),
+            },
+            DespawnOnExit(Screen::InGame),
+            Transform::from_translation(pos.extend(INK_SPLAT_Z)),
+            Visibility::default(),
+        ))
+        .with_children(|splat| {
+            splat.spawn(blob(Vec2::ZERO, 16.0));
+            for _ in 0..6 {
+                let direction = Vec2::from_angle(rng.random_range(0.0..TAU));
+                let distance = rng.random_range(14.0..32.0);
+                let radius = rng.random_range(2.0..7.0);
+                splat.spawn(blob(direction * distance, radius));
+            }
+        });
+}
+
+/// Knocks dying enemies back and topples them over, crossing them out in time with the beat,
+/// then despawns them
+fn animate_dying_enemies(
+    mut commands: Commands,
+    beat_index: Res<BeatIndex>,
+    position: Res<MusicalPosition>,
+    asset_handles: Res<StartupAssetHandles>,
+    atlases: Res<Assets<SpriteAtlas>>,
+    mut dying: Query<(Entity, &Dying, &mut Transform, &mut Sprite, &Children)>,
+    mut cross_outs: Query<(&CrossOut, &mut Transform, &mut Visibility), Without<Dying>>,
+) {
+    let beat_position = beat_index.0 as f32 + position.fraction;
+    let Some(atlas) = atlases.get(&asset_handles.sprite_atlas) else {
+        return;
+    };
+    let frames = atlas.get_frames("soldier dying");
+
+    for (enemy, dying, mut transform, mut sprite, children) in &mut dying {
+        let beats = beat_position - dying.start;
+        if beats >= DEATH_BEATS {
+            commands.entity(enemy).despawn();
+            continue;
+        }
+
+        // knocked back over the first beat, landing on the second
+        let knockback = EaseFunction::QuadraticOut.sample_clamped(beats);
+        let topple = EaseFunction::BounceOut.sample_clamped(beats - 1.0);
+        transform.translation = dying.from.lerp(dying.to, knockback).extend(ENEMY_Z);
+        transform.rotation = Quat::from_rotation_z(dying.tilt * topple);
+
+        // the frames are spread evenly over the animation, so they land on the half beats
+        if !frames.is_empty() {
+            let frame = (beats / DEATH_BEATS * frames.len() as f32) as usize;
+            sprite.rect = Some(frames[frame.min(frames.len() - 1)].as_rect());
+        }
+
+        for &child in children {
+            let Ok((cross_out, mut stroke_transform, mut visibility)) = cross_outs.get_mut(child)
+            else {
+                continue;
+            };
+
+            let drawn = (beats - cross_out.beat) / CROSS_OUT_STROKE_BEATS;
+            if drawn > 0.0 {
+                *visibility = Visibility::Inherited;
+                stroke_transform.scale.y = drawn.min(1.0);
+            }
+        }
+    }
+}
+
+fn fade_ink_splats(
+    mut commands: Commands,
+    beat_index: Res<BeatIndex>,
+    position: Res<MusicalPosition>,
+    splats: Query<(Entity, &InkSplat)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let beat_position = beat_index.0 as f32 + position.fraction;
+    let beats_per_bar = position.beats_per_bar.max(1) as f32;
+
+    for (entity, splat) in &splats {
+        let bars = (beat_position - splat.start) / beats_per_bar;
+        if bars >= INK_SPLAT_BARS {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        if let Some(material) = materials.get_mut(&splat.material) {
+            let alpha = 1.0 - EaseFunction::QuadraticIn.sample_clamped(bars / INK_SPLAT_BARS);
+            material.color = make_ink_color().with_alpha(alpha);
+        }
+    }
+}
+
+#[tweak_fn]
+fn update_enemy_lerp_dests(
+    on_beat: Res<OnBeat>,
+    position: Res<MusicalPosition>,
+    pacing: Res<EnemyPacing>,
+    enemies: Query<(&Transform, &LerpDestination, Option<&GotHit>), With<Enemy>>,
+    mut destinations: Query<&mut Transform, (With<EnemyLerpDest>, Without<Enemy>)>,
+) {
+    if !on_beat.0 || !position.starts_every(pacing.step_every) {
+        return;
+    }
+
+    let enemy_lerp_range = 75.0 * pacing.intensity;
+    let enemy_hit_lerp_range = 15.0;
+    for (enemy_transform, lerp_dest, got_hit) in &enemies {
+        let enemy_pos = enemy_transform.translation.xy();
+
+        let dir_to_origin = (-enemy_pos).try_normalize();
+        let Some(dir_to_origin) = dir_to_origin else {
+            continue;
+        };
+
+        let new_dest_pos = if got_hit.is_some() {
+            (-1.0 * dir_to_origin * enemy_hit_lerp_range) + enemy_pos
+        } else {
+            (dir_to_origin * enemy_lerp_range) + enemy_pos
+        };
+
+        let mut lerp_dest_transform = destinations.get_mut(*lerp_dest.collection()).unwrap();
+        lerp_dest_transform.translation.x = new_dest_pos.x;
+        lerp_dest_transform.translation.y = new_dest_pos.y;
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum EnemyKind {
+    Soldier,
+}
+
+impl EnemyKind {
+    fn sprite_name(self) -> &'static str {
+        match self {
+            EnemyKind::Soldier => "soldier",
+        }
+    }
+
+    pub(super) fn health(self) -> i32 {
+        match self {
+            EnemyKind::Soldier => 4,
+        }
+    }
+}
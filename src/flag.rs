@@ -0,0 +1,122 @@
+//! The flag, and losing or winning by it
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use inline_tweak::tweak_fn;
+
+use crate::assets::{SpriteAtlas, StartupAssetHandles};
+use crate::combat::{Damage, Died, GotHit};
+use crate::enemies::{Enemy, add_enemy_hits};
+use crate::health::{Health, Healthbar};
+use crate::timing::{BeatIndex, TrackTimer};
+use crate::{CombatSet, FLAG_Z, Screen};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(OnEnter(Screen::InGame), spawn_flag)
+        .add_systems(
+            Update,
+            (
+                add_flag_hits.in_set(CombatSet::Hit).after(add_enemy_hits),
+                check_victory.in_set(CombatSet::Resolve),
+            ),
+        )
+        .add_observer(lose_when_flag_dies);
+}
+
+fn spawn_flag(
+    mut commands: Commands,
+    asset_handles: Res<StartupAssetHandles>,
+    atlases: Res<Assets<SpriteAtlas>>,
+) {
+    let atlas = atlases.get(&asset_handles.sprite_atlas).unwrap();
+    let offsets = atlas.get_offsets_or_panic("flag");
+    let sprite = Sprite {
+        image: asset_handles.sprite_sheet.clone(),
+        rect: Some(offsets.as_rect()),
+        ..default()
+    };
+
+    commands.spawn((
+        Flag,
+        DespawnOnExit(Screen::InGame),
+        Anchor::CENTER,
+        sprite,
+        Transform::from_translation(Vec2::ZERO.extend(FLAG_Z)),
+        Health::new(8),
+        Healthbar::default(),
+    ));
+}
+
+#[derive(Component)]
+pub(super) struct Flag;
+
+#[tweak_fn]
+fn add_flag_hits(
+    mut commands: Commands,
+    // TODO use hitcircle again?
+    enemies: Query<(Entity, &Transform), (With<Enemy>, Without<GotHit>)>,
+    flags: Query<(Entity, &Transform, Option<&GotHitCooldown>), With<Flag>>,
+    beat_index: Res<BeatIndex>,
+) {
+    for (flag, flag_transform, cooldown) in &flags {
+        // TODO only damage on beat, but always show animation of some kind?
+        //   but they will pass over it after the beat
+        //   so really we just want a cooldown? on the flag, not on the enemies
+        let flag_pos = flag_transform.translation.xy();
+        let enemy_hit_radius = 20.0;
+        let mut hit_by = None;
+        for (enemy, enemy_transform) in &enemies {
+            let enemy_pos = enemy_transform.translation.xy();
+            let distance = enemy_pos.distance(flag_pos);
+            if distance > enemy_hit_radius {
+                continue;
+            }
+
+            hit_by = Some(enemy);
+            break;
+        }
+
+        if let Some(enemy) = hit_by
+            && cooldown.is_none()
+        {
+            commands
+                .entity(flag)
+                .insert((GotHit, GotHitCooldown(beat_index.0)));
+            commands.trigger(Damage {
+                target: flag,
+                amount: 1,
+                source: enemy,
+                judgement: None,
+            });
+        } else if let Some(cooldown_beat) = cooldown
+            && hit_by.is_none()
+            && beat_index.0 != cooldown_beat.0
+        {
+            commands.entity(flag).remove::<GotHitCooldown>();
+        }
+    }
+}
+
+fn lose_when_flag_dies(
+    died: On<Died>,
+    flags: Query<(), With<Flag>>,
+    mut next_screen: ResMut<NextState<Screen>>,
+) {
+    if flags.contains(died.entity) {
+        next_screen.set(Screen::GameOver);
+    }
+}
+
+fn check_victory(
+    flags: Query<&Health, With<Flag>>,
+    track_timer: Res<TrackTimer>,
+    mut next_screen: ResMut<NextState<Screen>>,
+) {
+    let flag_health = flags.single().unwrap();
+    if track_timer.0.is_finished() && flag_health.remaining > 0 {
+        next_screen.set(Screen::Victory);
+    }
+}
+
+#[derive(Component)]
+struct GotHitCooldown(usize);
@@ -0,0 +1,176 @@
+//! Health, and health bars for anything with a [`Healthbar`]
+
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::color::palettes::tailwind;
+use bevy::prelude::*;
+use inline_tweak::tweak_fn;
+
+use crate::UiSet;
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<HealthbarMesh>()
+        .add_observer(spawn_healthbar)
+        .add_systems(
+            Update,
+            (update_healthbar_fills, drain_healthbar_trails).in_set(UiSet),
+        );
+}
+
+/// The number of hits remaining until despawn
+#[derive(Component)]
+pub(super) struct Health {
+    pub(super) remaining: i32,
+    maximum: i32,
+}
+
+impl Health {
+    pub(super) fn new(max: i32) -> Self {
+        Self {
+            remaining: max,
+            maximum: max,
+        }
+    }
+
+    fn ratio(&self) -> f32 {
+        (self.remaining as f32 / self.maximum as f32).clamp(0.0, 1.0)
+    }
+}
+
+/// A bar showing its owner's [`Health`], spawned as children of the owner
+///
+/// After a hit, a trail lingers where the fill was and drains down to it,
+/// so the size of the hit stays readable.
+#[derive(Component, Clone, Copy)]
+pub(super) struct Healthbar {
+    /// the bar's center when full, relative to the owner
+    pub(super) offset: Vec3,
+    pub(super) fill: Color,
+    pub(super) trail: Color,
+}
+
+impl Default for Healthbar {
+    fn default() -> Self {
+        Self {
+            offset: Vec3::new(0.0, 48.0, 1.0),
+            fill: Color::Srgba(tailwind::RED_400),
+            trail: Color::Srgba(tailwind::RED_200),
+        }
+    }
+}
+
+/// the part of a health bar showing the remaining health
+#[derive(Component)]
+pub(super) struct HealthbarFill;
+
+/// the part of a health bar lagging behind the fill after a hit
+#[derive(Component)]
+pub(super) struct HealthbarTrail {
+    ratio: f32,
+    /// how long the trail waits after a hit before draining
+    hold: Timer,
+}
+
+const HEALTHBAR_RADIUS: f32 = 4.0;
+const HEALTHBAR_LENGTH: f32 = 32.0;
+/// draws the fill over the trail
+const HEALTHBAR_FILL_Z: f32 = 0.1;
+/// seconds the trail waits after a hit before draining
+const HEALTHBAR_TRAIL_HOLD: f32 = 0.4;
+/// the fraction of a full bar the trail drains per second
+const HEALTHBAR_TRAIL_DRAIN: f32 = 0.6;
+
+/// the mesh shared by every health bar, scaled to show how full it is
+#[derive(Resource)]
+struct HealthbarMesh(Handle<Mesh>);
+
+impl FromWorld for HealthbarMesh {
+    fn from_world(world: &mut World) -> Self {
+        let capsule = Capsule2d::new(HEALTHBAR_RADIUS, HEALTHBAR_LENGTH);
+        Self(world.resource_mut::<Assets<Mesh>>().add(capsule))
+    }
+}
+
+/// lays the capsule on its side, emptying towards the left
+#[tweak_fn]
+fn healthbar_transform(offset: Vec3, ratio: f32) -> Transform {
+    let full_length = HEALTHBAR_LENGTH + 2.0 * HEALTHBAR_RADIUS;
+    let emptied = full_length * (1.0 - ratio);
+
+    Transform {
+        translation: offset - Vec3::X * emptied / 2.0,
+        rotation: Quat::from_rotation_z(FRAC_PI_2), // 90 deg
+        scale: Vec3::new(1.0, ratio, 1.0),
+    }
+}
+
+fn spawn_healthbar(
+    add: On<Add, Healthbar>,
+    mut commands: Commands,
+    owners: Query<(&Healthbar, &Health)>,
+    mesh: Res<HealthbarMesh>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let Ok((healthbar, health)) = owners.get(add.entity) else {
+        return;
+    };
+
+    let ratio = health.ratio();
+    let fill_offset = healthbar.offset + Vec3::Z * HEALTHBAR_FILL_Z;
+    let hold = Timer::from_seconds(HEALTHBAR_TRAIL_HOLD, TimerMode::Once);
+
+    commands.entity(add.entity).with_children(|owner| {
+        owner.spawn((
+            HealthbarTrail { ratio, hold },
+            Mesh2d(mesh.0.clone()),
+            MeshMaterial2d(materials.add(healthbar.trail)),
+            healthbar_transform(healthbar.offset, ratio),
+        ));
+        owner.spawn((
+            HealthbarFill,
+            Mesh2d(mesh.0.clone()),
+            MeshMaterial2d(materials.add(healthbar.fill)),
+            healthbar_transform(fill_offset, ratio),
+        ));
+    });
+}
+
+fn update_healthbar_fills(
+    owners: Query<(&Healthbar, &Health), Changed<Health>>,
+    mut fills: Query<(&ChildOf, &mut Transform), With<HealthbarFill>>,
+) {
+    for (child_of, mut transform) in &mut fills {
+        let Ok((healthbar, health)) = owners.get(child_of.parent()) else {
+            continue;
+        };
+
+        let fill_offset = healthbar.offset + Vec3::Z * HEALTHBAR_FILL_Z;
+        *transform = healthbar_transform(fill_offset, health.ratio());
+    }
+}
+
+fn drain_healthbar_trails(
+    time: Res<Time>,
+    owners: Query<(&Healthbar, Ref<Health>)>,
+    mut trails: Query<(&ChildOf, &mut HealthbarTrail, &mut Transform)>,
+) {
+    for (child_of, mut trail, mut transform) in &mut trails {
+        let Ok((healthbar, health)) = owners.get(child_of.parent()) else {
+            continue;
+        };
+
+        if health.is_changed() {
+            trail.hold.reset();
+        }
+
+        let ratio = health.ratio();
+        if trail.ratio <= ratio {
+            trail.ratio = ratio;
+        } else if trail.hold.tick(time.delta()).is_finished() {
+            let drained = trail.ratio - HEALTHBAR_TRAIL_DRAIN * time.delta_secs();
+            trail.ratio = drained.max(ratio);
+        }
+
+        *transform = healthbar_transform(healthbar.offset, trail.ratio);
+    }
+}
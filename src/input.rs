@@ -0,0 +1,327 @@
+//! Reading the player's [`Intent`] from the mouse, keyboard, gamepads or touchscreen
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::replay::ReplayPlayback;
+use crate::settings::Settings;
+use crate::{InputSet, Screen};
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<Intent>()
+        .add_systems(OnExit(Screen::InGame), reset_intent)
+        .add_systems(
+            Update,
+            read_input
+                .in_set(InputSet)
+                .run_if(not(resource_exists::<ReplayPlayback>)),
+        );
+}
+
+#[derive(Resource, Default)]
+pub(super) struct Intent {
+    /// where the player is aiming the quill, in world space
+    pub(super) aim_pos: Vec2,
+    /// whether the player is currently scribbling
+    pub(super) quill_down: bool,
+    /// whether the player started scribbling this frame
+    pub(super) quill_pressed: bool,
+    /// The track time the scribble started at, when it's known better than this frame's
+    ///
+    /// Replays set this, so presses are judged the same at any frame rate.
+    pub(super) press_time: Option<f32>,
+}
+
+/// world units per second the aim moves with the keyboard or a gamepad stick
+const AIM_SPEED: f32 = 600.0;
+/// stick deflection below this is ignored
+const STICK_DEADZONE: f32 = 0.15;
+/// how far the aim moves per logical pixel a finger drags
+const TOUCH_DRAG_SCALE: f32 = 1.5;
+/// fingers tapped at once to pause
+const PAUSE_TOUCHES: usize = 3;
+/// pauses whatever else pause is bound to, so players can always find their way out
+pub(super) const PAUSE_KEY: KeyCode = KeyCode::Escape;
+
+/// Fills [`Intent`] from whichever of the mouse, keyboard, gamepads or touchscreen the player is using
+///
+/// Moving the mouse jumps the aim to the cursor; directional inputs move it from wherever it is.
+/// On touchscreens, a finger drags the aim like a trackpad and scribbles while it's held.
+/// Putting more fingers down is for gestures, like pausing, so it doesn't scribble.
+pub(super) fn read_input(
+    mut intent: ResMut<Intent>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera_query: Single<(&Camera, &GlobalTransform)>,
+    mut cursor_moved: MessageReader<CursorMoved>,
+    input: ActionInput,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    mut aim_touch: Local<Option<u64>>,
+) {
+    let touches = &input.touches;
+    if aim_touch.is_none_or(|id| touches.get_pressed(id).is_none()) {
+        *aim_touch = touches.iter_just_pressed().next().map(|touch| touch.id());
+    }
+    let single_touch = touches.iter().count() == 1;
+
+    let bindings = &settings.bindings;
+    intent.quill_down = input.pressed(&bindings.scribble) || single_touch;
+    intent.quill_pressed =
+        input.just_pressed(&bindings.scribble) || (single_touch && touches.any_just_pressed());
+    intent.press_time = None;
+
+    let (camera, camera_transform) = *camera_query;
+    let mouse_moved = cursor_moved.read().count() > 0;
+    if mouse_moved
+        && let Some(mouse_pos) = read_mouse_pos_in_world_space(&window, camera, camera_transform)
+    {
+        intent.aim_pos = mouse_pos;
+    }
+
+    let mut aim_delta = input.direction(bindings) * AIM_SPEED * time.delta_secs();
+    if let Some(touch) = aim_touch.and_then(|id| touches.get_pressed(id)) {
+        // screen space is y-down
+        let drag = touch.delta() * Vec2::new(1.0, -1.0);
+        aim_delta += drag * TOUCH_DRAG_SCALE;
+    }
+
+    if aim_delta != Vec2::ZERO {
+        let half_size = window.size() / 2.0;
+        intent.aim_pos = (intent.aim_pos + aim_delta).clamp(-half_size, half_size);
+    }
+}
+
+/// An input the player can bind to an action
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{key:?}"),
+            Binding::Mouse(button) => write!(f, "Mouse {button:?}"),
+            Binding::Gamepad(button) => write!(f, "Pad {button:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Scribble,
+    Pause,
+}
+
+impl Action {
+    pub(super) const ALL: [Action; 6] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::Scribble,
+        Action::Pause,
+    ];
+
+    pub(super) fn label(self) -> &'static str {
+        match self {
+            Action::Up => "Up",
+            Action::Down => "Down",
+            Action::Left => "Left",
+            Action::Right => "Right",
+            Action::Scribble => "Scribble",
+            Action::Pause => "Pause",
+        }
+    }
+}
+
+/// The inputs bound to each action; gamepad sticks always move the aim as well
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(default)]
+pub(super) struct Bindings {
+    up: Vec<Binding>,
+    down: Vec<Binding>,
+    left: Vec<Binding>,
+    right: Vec<Binding>,
+    pub(super) scribble: Vec<Binding>,
+    pub(super) pause: Vec<Binding>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Binding::*;
+
+        Self {
+            up: vec![
+                Key(KeyCode::KeyW),
+                Key(KeyCode::ArrowUp),
+                Gamepad(GamepadButton::DPadUp),
+            ],
+            down: vec![
+                Key(KeyCode::KeyS),
+                Key(KeyCode::ArrowDown),
+                Gamepad(GamepadButton::DPadDown),
+            ],
+            left: vec![
+                Key(KeyCode::KeyA),
+                Key(KeyCode::ArrowLeft),
+                Gamepad(GamepadButton::DPadLeft),
+            ],
+            right: vec![
+                Key(KeyCode::KeyD),
+                Key(KeyCode::ArrowRight),
+                Gamepad(GamepadButton::DPadRight),
+            ],
+            scribble: vec![
+                Mouse(MouseButton::Left),
+                Key(KeyCode::Space),
+                Gamepad(GamepadButton::South),
+            ],
+            pause: vec![Key(KeyCode::Escape), Gamepad(GamepadButton::Start)],
+        }
+    }
+}
+
+impl Bindings {
+    pub(super) fn get(&self, action: Action) -> &[Binding] {
+        match action {
+            Action::Up => &self.up,
+            Action::Down => &self.down,
+            Action::Left => &self.left,
+            Action::Right => &self.right,
+            Action::Scribble => &self.scribble,
+            Action::Pause => &self.pause,
+        }
+    }
+
+    fn get_mut(&mut self, action: Action) -> &mut Vec<Binding> {
+        match action {
+            Action::Up => &mut self.up,
+            Action::Down => &mut self.down,
+            Action::Left => &mut self.left,
+            Action::Right => &mut self.right,
+            Action::Scribble => &mut self.scribble,
+            Action::Pause => &mut self.pause,
+        }
+    }
+
+    /// the action an input is already bound to, if any
+    pub(super) fn action_for(&self, binding: Binding) -> Option<Action> {
+        Action::ALL
+            .into_iter()
+            .find(|&action| self.get(action).contains(&binding))
+    }
+
+    /// binds an action to another input, alongside the ones it already has
+    pub(super) fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.get_mut(action);
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+}
+
+/// Button state from every input device, for checking against [`Bindings`]
+#[derive(SystemParam)]
+pub(super) struct ActionInput<'w, 's> {
+    pub(super) keyboard: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    gamepads: Query<'w, 's, &'static Gamepad>,
+    touches: Res<'w, Touches>,
+}
+
+impl ActionInput<'_, '_> {
+    fn pressed(&self, bindings: &[Binding]) -> bool {
+        bindings.iter().any(|&binding| match binding {
+            Binding::Key(key) => self.keyboard.pressed(key),
+            Binding::Mouse(button) => self.mouse.pressed(button),
+            Binding::Gamepad(button) => self.gamepads.iter().any(|pad| pad.pressed(button)),
+        })
+    }
+
+    pub(super) fn just_pressed(&self, bindings: &[Binding]) -> bool {
+        bindings.iter().any(|&binding| match binding {
+            Binding::Key(key) => self.keyboard.just_pressed(key),
+            Binding::Mouse(button) => self.mouse.just_pressed(button),
+            Binding::Gamepad(button) => self.gamepads.iter().any(|pad| pad.just_pressed(button)),
+        })
+    }
+
+    /// whether any finger touched the screen this frame
+    pub(super) fn tapped(&self) -> bool {
+        self.touches.any_just_pressed()
+    }
+
+    /// whether enough fingers are down to count as the pause gesture, as of this frame
+    pub(super) fn pause_gesture(&self) -> bool {
+        self.tapped() && self.touches.iter().count() >= PAUSE_TOUCHES
+    }
+
+    /// the first input pressed this frame on any device
+    pub(super) fn any_just_pressed(&self) -> Option<Binding> {
+        let key = self
+            .keyboard
+            .get_just_pressed()
+            .next()
+            .copied()
+            .map(Binding::Key);
+        let mouse = || {
+            self.mouse
+                .get_just_pressed()
+                .next()
+                .copied()
+                .map(Binding::Mouse)
+        };
+        let gamepad = || {
+            self.gamepads
+                .iter()
+                .find_map(|pad| pad.get_just_pressed().next().copied())
+                .map(Binding::Gamepad)
+        };
+
+        key.or_else(mouse).or_else(gamepad)
+    }
+
+    /// the combined direction from the movement bindings and gamepad sticks, at most length 1
+    fn direction(&self, bindings: &Bindings) -> Vec2 {
+        let axis = |negative: &[Binding], positive: &[Binding]| {
+            self.pressed(positive) as i32 as f32 - self.pressed(negative) as i32 as f32
+        };
+        let mut direction = Vec2::new(
+            axis(&bindings.left, &bindings.right),
+            axis(&bindings.down, &bindings.up),
+        );
+
+        for pad in &self.gamepads {
+            let stick = pad.left_stick();
+            if stick.length() > STICK_DEADZONE {
+                direction += stick;
+            }
+        }
+
+        direction.clamp_length_max(1.0)
+    }
+}
+
+fn reset_intent(mut intent: ResMut<Intent>) {
+    *intent = Intent::default();
+}
+
+pub(super) fn read_mouse_pos_in_world_space(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Vec2> {
+    let viewport_position = window.cursor_position()?;
+    camera
+        .viewport_to_world_2d(camera_transform, viewport_position)
+        .ok()
+}
@@ -0,0 +1,126 @@
+//! Moving entities towards their lerp destinations
+
+use bevy::prelude::*;
+
+use crate::MovementSet;
+use crate::timing::{BeatIndex, MusicalPosition, Subdivision};
+
+/// The relation FROM a lerp destination TO the entity moving towards it
+#[derive(Component)]
+#[relationship(relationship_target = LerpDestination)]
+pub(super) struct LerpDestinationOf(pub Entity);
+
+/// The relation TO the destination an entity is moving towards,
+/// which is despawned along with it
+#[derive(Component)]
+#[relationship_target(relationship = LerpDestinationOf, linked_spawn)]
+pub(super) struct LerpDestination(Entity);
+
+/// Eases an entity towards its [`LerpDestination`] in time with the music
+///
+/// Each step of the subdivision starts a new ease from where the last step's ease was at the
+/// step boundary, so the path only depends on the music, not on when frames land.
+/// After a skipped step (or a rewind) that ease is stale, so it starts from where the entity is.
+/// Destinations should share the entity's parent, since this moves in local space.
+#[derive(Component)]
+pub(super) struct BeatLerp {
+    subdivision: Subdivision,
+    ease: EaseFunction,
+    /// how far through each step the entity arrives (exclusive of 0.0);
+    /// past 1.0, each step ends before the entity arrives and the next picks up from there
+    arrive_by: f32,
+    /// where the current ease started
+    from: Vec2,
+    /// where the current ease is heading, as of the last frame
+    to: Vec2,
+    /// the beat index and subdivision step the current ease started on
+    step: Option<(usize, usize)>,
+}
+
+impl BeatLerp {
+    pub(super) fn new(subdivision: Subdivision, ease: EaseFunction, arrive_by: f32) -> Self {
+        Self {
+            subdivision,
+            ease,
+            arrive_by,
+            from: Vec2::ZERO,
+            to: Vec2::ZERO,
+            step: None,
+        }
+    }
+}
+
+/// Follows a [`LerpDestination`] with exponential smoothing
+///
+/// The smoothing is scaled by the frame time, so it moves the same at any frame rate.
+#[derive(Component)]
+pub(super) struct SmoothLerp {
+    /// seconds to close half the distance to the destination
+    pub(super) half_life: f32,
+}
+
+/// moving entities towards their lerp destinations, within [`MovementSet`]
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LerpSet;
+
+pub(super) fn plugin(app: &mut App) {
+    app.configure_sets(Update, LerpSet.in_set(MovementSet))
+        .add_systems(Update, (move_beat_lerpers, smooth_lerpers).in_set(LerpSet));
+}
+
+fn move_beat_lerpers(
+    beat_index: Res<BeatIndex>,
+    position: Res<MusicalPosition>,
+    mut lerpers: Query<(&mut BeatLerp, &LerpDestination, &mut Transform)>,
+    destinations: Query<&Transform, (With<LerpDestinationOf>, Without<BeatLerp>)>,
+) {
+    for (mut lerp, lerp_dest, mut transform) in &mut lerpers {
+        let Ok(dest_transform) = destinations.get(lerp_dest.0) else {
+            continue;
+        };
+
+        let steps_per_beat = lerp.subdivision.steps_per_beat();
+        let step = (beat_index.0, position.subdivision_step(lerp.subdivision));
+        if lerp.step != Some(step) {
+            let steps_since = |(beat, sub_step): (usize, usize)| beat * steps_per_beat + sub_step;
+            let follows_last_step = lerp
+                .step
+                .is_some_and(|last| steps_since(last) + 1 == steps_since(step));
+
+            // picking up from the last frame's position instead would depend on the frame rate
+            lerp.from = if follows_last_step {
+                let boundary_progress = lerp.ease.sample_clamped(1.0 / lerp.arrive_by);
+                lerp.from.lerp(lerp.to, boundary_progress)
+            } else {
+                transform.translation.xy()
+            };
+            lerp.step = Some(step);
+        }
+        lerp.to = dest_transform.translation.xy();
+
+        let step_phase = position.fraction * steps_per_beat as f32 - step.1 as f32;
+        let progress = lerp.ease.sample_clamped(step_phase / lerp.arrive_by);
+
+        let moved = lerp.from.lerp(lerp.to, progress);
+        transform.translation.x = moved.x;
+        transform.translation.y = moved.y;
+    }
+}
+
+fn smooth_lerpers(
+    time: Res<Time>,
+    mut lerpers: Query<(&SmoothLerp, &LerpDestination, &mut Transform)>,
+    destinations: Query<&Transform, (With<LerpDestinationOf>, Without<SmoothLerp>)>,
+) {
+    for (smooth, lerp_dest, mut transform) in &mut lerpers {
+        let Ok(dest_transform) = destinations.get(lerp_dest.0) else {
+            continue;
+        };
+
+        let remaining = 0.5_f32.powf(time.delta_secs() / smooth.half_life);
+        let dest_pos = dest_transform.translation.xy();
+        let moved = dest_pos.lerp(transform.translation.xy(), remaining);
+        transform.translation.x = moved.x;
+        transform.translation.y = moved.y;
+    }
+}
@@ -1,17 +1,32 @@
-use std::f32::consts::FRAC_PI_2;
-use std::time::Duration;
-
 use bevy::asset::AssetMetaCheck;
-use bevy::color::palettes::tailwind;
-use bevy::ecs::spawn::SpawnWith;
 use bevy::prelude::*;
-use bevy::sprite::Anchor;
-use bevy::window::PrimaryWindow;
-
-use bevy_asset_loader::prelude::*;
-use bevy_common_assets::json::JsonAssetPlugin;
-use bevy_kira_audio::prelude::*;
-use inline_tweak::*;
+use bevy_kira_audio::AudioPlugin;
+
+use crate::pause::Pause;
+
+mod assets;
+mod beats;
+mod calibration;
+mod chart;
+mod combat;
+mod controls;
+#[cfg(feature = "dev")]
+mod editor;
+mod enemies;
+mod flag;
+mod health;
+mod input;
+mod lerp;
+mod pause;
+mod quill;
+mod replay;
+mod score;
+mod settings;
+#[cfg(test)]
+mod sim;
+mod songs;
+mod timing;
+mod ui;
 
 fn main() -> AppExit {
     App::new()
@@ -35,83 +50,103 @@ fn main() -> AppExit {
                 }),
         )
         .init_state::<Screen>()
-        .add_loading_state(
-            LoadingState::new(Screen::Loading)
-                .continue_to_state(Screen::Menu)
-                .load_collection::<StartupAssetHandles>(),
-        )
         .add_plugins((
+            #[cfg(feature = "dev")]
+            editor::plugin,
             AudioPlugin,
-            JsonAssetPlugin::<Beats>::new(&["beats.json"]),
-            JsonAssetPlugin::<SpriteAtlas>::new(&["atlas.json"]),
+            assets::plugin,
+            gameplay_plugin,
+            ui::plugin,
+            songs::plugin,
+            settings::plugin,
+            calibration::plugin,
+            controls::plugin,
+            replay::plugin,
         ))
-        .add_systems(OnEnter(Screen::Menu), (spawn_camera, spawn_main_menu))
-        .add_systems(
-            OnEnter(Screen::InGame),
-            (
-                play_scherzo,
-                init_beat_timer,
-                spawn_flag,
-                spawn_quill,
-            ),
-        )
-        .insert_resource(ClearColor(Color::Srgba(tailwind::GRAY_200)))
-        .init_resource::<Intent>()
-        .init_resource::<BeatIndex>()
-        .insert_resource(TrackTimer::new())
-        .init_resource::<BeatTimer>()
-        .init_resource::<OnBeat>()
-        .add_systems(
-            Update,
+        .add_plugins((
+            timing::track_plugin,
+            timing::plugin,
+            pause::plugin,
+            input::plugin,
+            quill::plugin,
+            combat::plugin,
+            enemies::plugin,
+            flag::plugin,
+            health::plugin,
+            lerp::plugin,
+            score::plugin,
+        ))
+        .run()
+}
+
+/// Ordering of the music clock, which keeps running through count-ins
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BeatSet {
+    /// advancing the track timer along with the audio
+    Track,
+    /// deriving the beat and musical position from the track timer
+    Tick,
+    /// visuals that follow the beat
+    Pulse,
+}
+
+/// reading the player's intent for this frame
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InputSet;
+
+/// Ordering of scribbles, hits and their outcomes
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CombatSet {
+    /// judging the timing of new scribbles
+    Judge,
+    /// placing this frame's hit circles
+    Attack,
+    /// applying hits to whatever the hit circles or enemies touched
+    Hit,
+    /// winning or losing
+    Resolve,
+}
+
+/// spawning enemies, from the chart or at random
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpawnSet;
+
+/// moving the quill and enemies
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MovementSet;
+
+/// despawning whatever is used up or defeated
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CleanupSet;
+
+/// updating the in-game hud
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UiSet;
+
+/// The in-game pause state, and the order of the gameplay sets within a frame
+fn gameplay_plugin(app: &mut App) {
+    app.add_sub_state::<Pause>().configure_sets(
+        Update,
+        (
+            (BeatSet::Track, BeatSet::Tick, BeatSet::Pulse)
+                .chain()
+                .run_if(in_state(Screen::InGame).and(not(in_state(Pause::Paused)))),
             (
-                // timers
-                tick_track_timer,
-                tick_beat_timer,
-                quill_reticle_size_beat,
-                // interaction
-                read_input,
-                remove_hit_circles,
-                remove_got_hit,
-                add_player_hit_circle,
-                add_enemy_hits,
-                add_flag_hits,
-                check_game_over,
-                // enemies
-                spawn_enemies,
-                update_enemy_lerp_dests,
-                move_enemies,
-                // player
-                move_quill_reticle,
-                move_quill_target,
-                move_quill,
-                rotate_quill_sprite,
-                drop_ink_behind_quill,
-                // cleanup
-                despawn_old_ink,
-                despawn_zero_health_enemies,
+                InputSet,
+                CombatSet::Judge,
+                CombatSet::Attack,
+                CombatSet::Hit,
+                CombatSet::Resolve,
+                SpawnSet,
+                MovementSet,
+                CleanupSet,
+                UiSet,
             )
                 .chain()
-                .run_if(in_state(Screen::InGame)),
+                .run_if(in_state(Pause::Running)),
         )
-        .run()
-}
-
-/// handles to the assets loaded on game start
-#[derive(AssetCollection, Resource)]
-struct StartupAssetHandles {
-    #[expect(unused)]
-    #[asset(path = "images/Eroica_Beethoven_title.jpg")]
-    eroica_score: Handle<Image>,
-
-    #[asset(path = "audio/03_scherzo.flac")]
-    scherzo: Handle<AudioSource>,
-    #[asset(path = "audio/03_scherzo.beats.json")]
-    scherzo_beats: Handle<Beats>,
-
-    #[asset(path = "sprites/sprite_sheet.png")]
-    sprite_sheet: Handle<Image>,
-    #[asset(path = "sprites/sprite_sheet.atlas.json")]
-    sprite_atlas: Handle<SpriteAtlas>,
+            .chain(),
+    );
 }
 
 #[derive(States, Default, Debug, Clone, PartialEq, Eq, Hash)]
@@ -120,893 +155,18 @@ enum Screen {
     Loading,
     // TODO change loading transitions
     Menu,
+    Calibration,
+    Controls,
+    SongSelect,
+    SongLoading,
+    #[cfg(feature = "dev")]
+    Editor,
     InGame,
     GameOver,
+    Victory,
 }
 
-fn spawn_camera(mut commands: Commands) {
-    commands.spawn(Camera2d);
-}
-
-fn play_scherzo(assets: Res<StartupAssetHandles>, audio: Res<Audio>) {
-    audio.play(assets.scherzo.clone());
-}
-
-#[derive(Component)]
-struct QuillReticle;
-
-#[derive(Component)]
-struct Quill;
-
-#[derive(Component)]
-struct QuillTarget;
-
-const RETICLE_BIG_INNER_RADIUS: f32 = 30.0;
-const RETICLE_BIG_OUTER_RADIUS: f32 = 40.0;
-
 const RETICLE_Z: f32 = 15.0;
 const INK_Z: f32 = 10.0;
 const ENEMY_Z: f32 = 5.0;
 const FLAG_Z: f32 = 0.0;
-
-fn spawn_quill(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    asset_handles: Res<StartupAssetHandles>,
-    atlases: Res<Assets<SpriteAtlas>>,
-) {
-    let mesh = meshes.add(make_reticle(1.0));
-    let color = make_reticle_color(1.0);
-    let translation = Vec3::new(0.0, 0.0, RETICLE_Z);
-
-    let atlas = atlases.get(&asset_handles.sprite_atlas).unwrap();
-    let offsets = atlas.get_offsets_or_panic("quill");
-
-    let sprite = Sprite {
-        image: asset_handles.sprite_sheet.clone(),
-        rect: Some(offsets.as_rect()),
-        ..default()
-    };
-
-    commands.spawn((
-        QuillReticle,
-        Mesh2d(mesh),
-        MeshMaterial2d(materials.add(color)),
-        Transform::from_translation(translation),
-        Visibility::default(),
-        children![
-            (Quill, Anchor::BOTTOM_LEFT, sprite, Transform::default()),
-            (QuillTarget, Transform::default()),
-        ],
-    ));
-}
-
-fn spawn_flag(
-    mut commands: Commands,
-    asset_handles: Res<StartupAssetHandles>,
-    atlases: Res<Assets<SpriteAtlas>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-) {
-    let atlas = atlases.get(&asset_handles.sprite_atlas).unwrap();
-    let offsets = atlas.get_offsets_or_panic("flag");
-    let sprite = Sprite {
-        image: asset_handles.sprite_sheet.clone(),
-        rect: Some(offsets.as_rect()),
-        ..default()
-    };
-
-    let healthbar_capsule = make_healthbar_capsule(1.0);
-    let healthbar_color = Color::Srgba(tailwind::RED_400);
-    let healthbar_transform = Transform {
-        translation: Vec3::new(0.0, 48.0, FLAG_Z + 1.0),
-        rotation: Quat::from_rotation_z(FRAC_PI_2), // 90 deg
-        scale: Vec3::ONE,
-    };
-
-    commands.spawn((
-        Flag,
-        Anchor::CENTER,
-        sprite,
-        Transform::from_translation(Vec2::ZERO.extend(FLAG_Z)),
-        Health::new(8),
-        children![(
-            Healthbar,
-            Mesh2d(meshes.add(healthbar_capsule)),
-            MeshMaterial2d(materials.add(healthbar_color)),
-            healthbar_transform
-        )],
-    ));
-}
-
-#[derive(Component)]
-struct Flag;
-
-#[derive(Component)]
-#[component(storage = "SparseSet")]
-struct HitCircle {
-    radius: f32,
-}
-
-#[derive(Component)]
-#[component(storage = "SparseSet")]
-struct GotHit;
-
-#[tweak_fn]
-fn add_enemy_hits(
-    mut commands: Commands,
-    mut enemies: Query<(Entity, &Transform, &mut Health, &Children), With<Enemy>>,
-    on_beat: Res<OnBeat>,
-    hit_circles: Query<(&HitCircle, &Transform), Without<Enemy>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    health_bars: Query<Entity, With<Healthbar>>,
-) {
-    for (enemy, enemy_transform, mut health, children) in &mut enemies {
-        let enemy_pos = enemy_transform.translation.xy();
-
-        let mut hit = false;
-        for (hit_circle, hit_transform) in &hit_circles {
-            let center = hit_transform.translation.xy();
-            let distance = enemy_pos.distance(center);
-            if distance < hit_circle.radius {
-                hit = true;
-                break;
-            }
-        }
-
-        if hit {
-            commands.entity(enemy).insert(GotHit);
-            if on_beat.0 {
-                health.remaining -= 1;
-            }
-
-            // TODO there has to be a better way to do this
-            //   try to be general across all heathbars
-            //   make a separate system that uses change detection?
-            //   use an observer?
-            for &child in children {
-                update_health_bar(&mut health, &mut commands, child, &mut meshes, health_bars);
-            }
-        }
-    }
-}
-
-// TODO this should be a system probably
-fn update_health_bar(
-    health: &mut Health,
-    commands: &mut Commands,
-    child: Entity,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    health_bars: Query<Entity, With<Healthbar>>,
-) {
-    let Ok(health_bar) = health_bars.get(child) else {
-        return;
-    };
-
-    let capsule = make_healthbar_capsule(health.remaining as f32 / health.maximum as f32);
-
-    commands
-        .entity(health_bar)
-        .insert(Mesh2d(meshes.add(capsule)));
-}
-
-#[tweak_fn]
-fn add_flag_hits(
-    mut commands: Commands,
-    // TODO use hitcircle again?
-    enemies: Query<&Transform, (With<Enemy>, Without<GotHit>)>,
-    mut flags: Query<
-        (
-            Entity,
-            &Transform,
-            Option<&GotHitCooldown>,
-            &mut Health,
-            &Children,
-        ),
-        With<Flag>,
-    >,
-    beat_index: Res<BeatIndex>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    health_bars: Query<Entity, With<Healthbar>>,
-) {
-    for (flag, flag_transform, cooldown, mut flag_health, children) in &mut flags {
-        // TODO only damage on beat, but always show animation of some kind?
-        //   but they will pass over it after the beat
-        //   so really we just want a cooldown? on the flag, not on the enemies
-        let flag_pos = flag_transform.translation.xy();
-        let enemy_hit_radius = 20.0;
-        let mut hit = false;
-        for enemy_transform in &enemies {
-            let enemy_pos = enemy_transform.translation.xy();
-            let distance = enemy_pos.distance(flag_pos);
-            if distance > enemy_hit_radius {
-                continue;
-            }
-
-            hit = true;
-            break;
-        }
-
-        if hit && cooldown.is_none() {
-            commands
-                .entity(flag)
-                .insert((GotHit, GotHitCooldown(beat_index.0)));
-            flag_health.remaining -= 1;
-            for &child in children {
-                update_health_bar(
-                    &mut flag_health,
-                    &mut commands,
-                    child,
-                    &mut meshes,
-                    health_bars,
-                );
-            }
-            // let thing = children.get(asdf)
-            // TODO also change mesh
-        } else if let Some(cooldown_beat) = cooldown
-            && !hit
-        {
-            if beat_index.0 != cooldown_beat.0 {
-                commands.entity(flag).remove::<GotHitCooldown>();
-            }
-        }
-    }
-}
-
-fn check_game_over(flags: Query<&Health, With<Flag>>, mut next_screen: ResMut<NextState<Screen>>) {
-    let flag_health = flags.single().unwrap();
-    if flag_health.remaining <= 0 {
-        next_screen.set(Screen::GameOver);
-    }
-}
-
-#[derive(Component)]
-struct GotHitCooldown(usize);
-
-#[tweak_fn]
-fn remove_hit_circles(mut commands: Commands, hit_circles: Query<Entity, With<HitCircle>>) {
-    for hit_circle in &hit_circles {
-        commands.entity(hit_circle).despawn();
-    }
-}
-
-#[tweak_fn]
-fn remove_got_hit(mut commands: Commands, got_hits: Query<Entity, With<GotHit>>) {
-    for ent in &got_hits {
-        commands.entity(ent).remove::<GotHit>();
-    }
-}
-
-#[tweak_fn]
-fn add_player_hit_circle(
-    mut commands: Commands,
-    intent: Res<Intent>,
-    quills: Query<&Transform, With<QuillReticle>>,
-) {
-    if !intent.quill_down {
-        return;
-    }
-
-    let Ok(quill_transform) = quills.single().cloned() else {
-        return;
-    };
-
-    commands.spawn((
-        HitCircle {
-            radius: SCRIBBLE_HORIZONTAL_RANGE,
-        },
-        quill_transform,
-    ));
-}
-
-#[tweak_fn]
-fn make_reticle(ratio: f32) -> Annulus {
-    Annulus::new(
-        ratio * RETICLE_BIG_INNER_RADIUS,
-        ratio * RETICLE_BIG_OUTER_RADIUS,
-    )
-}
-
-#[tweak_fn]
-fn make_reticle_color(ratio: f32) -> Color {
-    let lightness = (1.0 - ratio) * 0.5 + 0.2;
-    Color::hsl(200.0, 0.95, lightness)
-}
-
-#[tweak_fn]
-fn quill_reticle_size_beat(
-    mut reticles: Query<(&mut Mesh2d, &mut MeshMaterial2d<ColorMaterial>), With<QuillReticle>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    beat_timer: Res<BeatTimer>,
-) {
-    let ratio = beat_timer.angle_wave();
-
-    let shape = make_reticle(ratio);
-    let color = make_reticle_color(ratio);
-    for (mut mesh, mut material) in &mut reticles {
-        mesh.0 = meshes.add(shape);
-        material.0 = materials.add(color);
-    }
-}
-
-#[derive(Resource, Default)]
-struct Intent {
-    /// the mouse position in world space
-    mouse_pos: Option<Vec2>,
-    /// whether the player is currently scribbling
-    quill_down: bool,
-}
-
-fn read_input(
-    mut intent: ResMut<Intent>,
-    window: Single<&Window, With<PrimaryWindow>>,
-    camera_query: Single<(&Camera, &GlobalTransform)>,
-    mouse: Res<ButtonInput<MouseButton>>,
-) {
-    let (camera, camera_transform) = *camera_query;
-    intent.quill_down = mouse.pressed(MouseButton::Left);
-    intent.mouse_pos = read_mouse_pos_in_world_space(&window, camera, camera_transform);
-}
-
-fn read_mouse_pos_in_world_space(
-    window: &Window,
-    camera: &Camera,
-    camera_transform: &GlobalTransform,
-) -> Option<Vec2> {
-    let viewport_position = window.cursor_position()?;
-    camera
-        .viewport_to_world_2d(camera_transform, viewport_position)
-        .ok()
-}
-
-#[tweak_fn]
-fn move_quill_reticle(
-    intent: Res<Intent>,
-    mut reticles: Query<&mut Transform, With<QuillReticle>>,
-) {
-    let Some(mouse_pos) = intent.mouse_pos else {
-        return;
-    };
-
-    let reticle_lerp_speed = 0.2;
-    for mut transform in &mut reticles {
-        let pos = transform.translation.xy();
-        let moved = pos.lerp(mouse_pos, reticle_lerp_speed);
-        transform.translation.x = moved.x;
-        transform.translation.y = moved.y;
-    }
-}
-
-const SCRIBBLE_HORIZONTAL_RANGE: f32 = 100.0;
-
-#[tweak_fn]
-fn move_quill_target(
-    intent: Res<Intent>,
-    beat_index: Res<BeatIndex>,
-    on_beat: Res<OnBeat>,
-    mut quill_targets: Query<&mut Transform, With<QuillTarget>>,
-) {
-    let mut target_transform = quill_targets.single_mut().unwrap();
-
-    if intent.quill_down {
-        let even_beat = beat_index.0 % 2 == 0;
-        let x_dir = if even_beat { 1.0 } else { -1.0 };
-        let x = x_dir * SCRIBBLE_HORIZONTAL_RANGE;
-
-        let y = if on_beat.0 {
-            let vertical_range = 30.0;
-            use rand::prelude::*;
-            let mut rng = rand::rng();
-            rng.random_range(-vertical_range..vertical_range)
-        } else {
-            target_transform.translation.y
-        };
-
-        target_transform.translation = Vec3::new(x, y, 0.0);
-    } else {
-        target_transform.translation = Vec3::ZERO;
-    }
-}
-
-#[tweak_fn]
-fn move_quill(
-    quill_targets: Query<&Transform, With<QuillTarget>>,
-    mut quills: Query<&mut Transform, (With<Quill>, Without<QuillTarget>)>,
-) {
-    let quill_lerp_speed = 0.2;
-
-    let quill_target_transform = quill_targets.single().unwrap();
-    let target_pos = quill_target_transform.translation.xy();
-    let mut quill_transform = quills.single_mut().unwrap();
-    let pos = quill_transform.translation.xy();
-    let moved = pos.lerp(target_pos, quill_lerp_speed);
-
-    quill_transform.translation = moved.extend(0.0);
-}
-
-#[tweak_fn]
-fn rotate_quill_sprite(mut quills: Query<&mut Transform, With<Quill>>) {
-    let mut quill_transform = quills.single_mut().unwrap();
-
-    let x_neg_one_to_one = quill_transform.translation.x / SCRIBBLE_HORIZONTAL_RANGE;
-    let mut angle_radians = x_neg_one_to_one * -1.0;
-    let angle_modifier = if angle_radians < 0.0 { 0.25 } else { 0.75 };
-    angle_radians *= angle_modifier;
-
-    quill_transform.rotation = Quat::from_rotation_z(angle_radians);
-}
-
-#[derive(Component)]
-struct Ink {
-    spawn_beat: usize,
-}
-
-#[tweak_fn]
-fn make_ink_color() -> Color {
-    make_reticle_color(0.75)
-}
-
-#[tweak_fn]
-fn drop_ink_behind_quill(
-    beat_index: Res<BeatIndex>,
-    quills: Query<&GlobalTransform, With<Quill>>,
-    mut last_quill_pos_maybe: Local<Option<Vec2>>,
-    intent: Res<Intent>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut commands: Commands,
-) {
-    let quill_position = quills.single().unwrap().translation();
-
-    match *last_quill_pos_maybe {
-        None => {
-            *last_quill_pos_maybe = Some(quill_position.xy());
-        }
-
-        Some(last_quill_pos) => {
-            let quill_pos = quill_position.xy();
-            let capsule_rotation = {
-                match (quill_pos - last_quill_pos).try_normalize() {
-                    None => Quat::default(),
-                    Some(mouse_direction) => {
-                        let capsule_angle = Vec2::Y.angle_to(mouse_direction);
-                        Quat::from_rotation_z(capsule_angle)
-                    }
-                }
-            };
-
-            let capsule_pos = quill_pos.midpoint(last_quill_pos);
-            let capsule_transform = Transform {
-                translation: capsule_pos.extend(INK_Z),
-                rotation: capsule_rotation,
-                ..default()
-            };
-
-            let capsule_len = quill_pos.distance(last_quill_pos).abs();
-            let capsule_radius = 5.0;
-            let capsule = Capsule2d::new(capsule_radius, capsule_len);
-
-            *last_quill_pos_maybe = Some(quill_pos);
-            if !intent.quill_down {
-                return;
-            }
-
-            let spawn_beat = beat_index.0;
-            let mesh = meshes.add(capsule);
-            let color = make_ink_color();
-
-            commands.spawn((
-                Ink { spawn_beat },
-                Mesh2d(mesh.clone()),
-                MeshMaterial2d(materials.add(color)),
-                capsule_transform,
-            ));
-        }
-    }
-}
-
-#[tweak_fn]
-fn despawn_old_ink(
-    beat_index: Res<BeatIndex>,
-    inks: Query<(Entity, &Ink)>,
-    mut commands: Commands,
-) {
-    for (entity, ink) in &inks {
-        let age_beats = beat_index.0 - ink.spawn_beat;
-        let despawn_after_beats = 2;
-        if age_beats > despawn_after_beats {
-            commands.entity(entity).despawn();
-        }
-    }
-}
-
-/// Elapsed time of the current music track
-#[derive(Resource, Default)]
-struct TrackTimer(Timer);
-
-impl TrackTimer {
-    fn new() -> Self {
-        // TODO use actual length of the track
-        let timer = Timer::new(Duration::from_mins(100), TimerMode::Once);
-
-        Self(timer)
-    }
-}
-
-fn tick_track_timer(time: Res<Time>, mut track_timer: ResMut<TrackTimer>) {
-    track_timer.0.tick(time.delta());
-}
-
-#[derive(serde::Deserialize, bevy::asset::Asset, bevy::reflect::TypePath)]
-struct Beats {
-    beats: Vec<f32>,
-    #[expect(unused)]
-    beats_intervals: Vec<f32>,
-}
-
-#[derive(Resource, Default)]
-struct BeatIndex(usize);
-
-#[derive(Resource, Default)]
-struct BeatTimer(Timer);
-
-impl BeatTimer {
-    fn from_index(beat_index: usize, track_time: f32, beats: &Beats) -> Self {
-        let next_beat_index = beat_index + 1;
-        if next_beat_index > beats.beats.len() {
-            log::warn!("invalid beat index");
-            return Default::default();
-        }
-
-        let next_beat = beats.beats[next_beat_index];
-        let to_next_beat = next_beat - track_time;
-        let duration = Duration::from_secs_f32(to_next_beat);
-        let timer = Timer::new(duration, TimerMode::Once);
-
-        Self(timer)
-    }
-
-    // how far through the beat we are, 0.0-1.0
-    fn elapsed_ratio(&self) -> f32 {
-        self.0.elapsed_secs() / self.0.duration().as_secs_f32()
-    }
-
-    // 0.0-1.0
-    // 0.0 == on downbeat, 1.0 = up between beats
-    // TODO replace this with an actual curved wave of some kind
-    //   faster on the way down?
-    fn angle_wave(&self) -> f32 {
-        let one_on_beat = (0.5 - self.elapsed_ratio()).abs() * 0.5 + 0.5;
-        #[allow(clippy::let_and_return)]
-        let zero_on_beat = 1.0 - one_on_beat;
-        zero_on_beat
-    }
-}
-
-fn init_beat_timer(
-    assets: Res<StartupAssetHandles>,
-    beats_assets: Res<Assets<Beats>>,
-    mut beat_timer: ResMut<BeatTimer>,
-) {
-    let beats = beats_assets.get(&assets.scherzo_beats).unwrap();
-    *beat_timer = BeatTimer::from_index(0, 0.0, beats);
-}
-
-#[derive(Resource, Default)]
-struct OnBeat(bool);
-
-fn tick_beat_timer(
-    time: Res<Time>,
-    track_timer: Res<TrackTimer>,
-    assets: Res<StartupAssetHandles>,
-    beats_assets: Res<Assets<Beats>>,
-    mut beat_index: ResMut<BeatIndex>,
-    mut beat_timer: ResMut<BeatTimer>,
-    mut on_beat: ResMut<OnBeat>,
-) {
-    beat_timer.0.tick(time.delta());
-    on_beat.0 = beat_timer.0.just_finished();
-    if on_beat.0 {
-        beat_index.0 += 1;
-        let beats = beats_assets.get(&assets.scherzo_beats).unwrap();
-        let track_time = track_timer.0.elapsed_secs();
-        *beat_timer = BeatTimer::from_index(beat_index.0, track_time, beats);
-    }
-}
-
-// Aseprite integration
-
-#[derive(serde::Deserialize, bevy::asset::Asset, bevy::reflect::TypePath)]
-struct SpriteAtlas {
-    #[expect(unused)]
-    meta: SpriteAtlasMeta,
-    frames: Vec<SpriteFrame>,
-}
-
-impl SpriteAtlas {
-    fn get_offsets_or_panic<'s>(&'s self, name: &str) -> &'s SpriteAtlasFrameOffsets {
-        let frame = self
-            .frames
-            .iter()
-            .find(|f| f.filename.starts_with(name))
-            .unwrap();
-
-        &frame.frame
-    }
-}
-
-#[expect(unused)]
-#[derive(serde::Deserialize)]
-struct SpriteAtlasMeta {
-    size: SpriteAtlasSize,
-}
-
-#[expect(unused)]
-#[derive(serde::Deserialize)]
-struct SpriteAtlasSize {
-    w: usize,
-    h: usize,
-}
-
-#[derive(serde::Deserialize)]
-struct SpriteFrame {
-    filename: String,
-    frame: SpriteAtlasFrameOffsets,
-    // we don't want to use this literally
-    // but do we still want it for handling proportions?
-    #[expect(unused)]
-    duration: u64,
-}
-
-#[derive(serde::Deserialize)]
-struct SpriteAtlasFrameOffsets {
-    x: usize,
-    y: usize,
-    w: usize,
-    h: usize,
-}
-
-impl SpriteAtlasFrameOffsets {
-    fn as_rect(&self) -> Rect {
-        let min = Vec2::new(self.x as f32, self.y as f32);
-
-        let max_x = self.x as f32 + self.w as f32;
-        let max_y = self.y as f32 + self.h as f32;
-        let max = Vec2::new(max_x, max_y);
-
-        Rect { min, max }
-    }
-}
-
-#[derive(Component)]
-struct Enemy;
-
-/// The number of hits remaining until despawn
-#[derive(Component)]
-struct Health {
-    remaining: i32,
-    maximum: i32,
-}
-
-#[derive(Component)]
-struct Healthbar;
-
-impl Health {
-    fn new(max: i32) -> Self {
-        Self {
-            remaining: max,
-            maximum: max,
-        }
-    }
-}
-
-#[derive(Component)]
-struct EnemyLerpDest;
-
-#[tweak_fn]
-fn spawn_enemies(
-    on_beat: Res<OnBeat>,
-    beat_index: Res<BeatIndex>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut commands: Commands,
-    asset_handles: Res<StartupAssetHandles>,
-    atlases: Res<Assets<SpriteAtlas>>,
-) {
-    let on_beat_4 = beat_index.0 % 4 == 0;
-    if !on_beat_4 || !on_beat.0 {
-        return;
-    }
-
-    let enemy_pos = {
-        use rand::prelude::*;
-        let mut rng = rand::rng();
-
-        let mut x = rng.random_range(250.0..500.0);
-        if rng.random_bool(0.5) {
-            x *= -1.0;
-        }
-
-        let mut y = rng.random_range(250.0..500.0);
-        if rng.random_bool(0.5) {
-            y *= -1.0;
-        }
-
-        Vec2::new(x, y)
-    };
-
-    let atlas = atlases.get(&asset_handles.sprite_atlas).unwrap();
-    let offsets = atlas.get_offsets_or_panic("soldier");
-    let sprite = Sprite {
-        image: asset_handles.sprite_sheet.clone(),
-        rect: Some(offsets.as_rect()),
-        flip_x: enemy_pos.x > 0.0,
-        ..default()
-    };
-
-    // TODO make reusable; use an observer & spawner pattern?
-    let healthbar_capsule = make_healthbar_capsule(1.0);
-    let healthbar_color = Color::Srgba(tailwind::RED_400);
-    let healthbar_transform = Transform {
-        translation: Vec3::new(0.0, 48.0, ENEMY_Z + 1.0),
-        rotation: Quat::from_rotation_z(FRAC_PI_2), // 90 deg
-        scale: Vec3::ONE,
-    };
-
-    let lerp_dest = commands
-        .spawn((
-            EnemyLerpDest,
-            Transform::from_translation(enemy_pos.extend(ENEMY_Z)),
-        ))
-        .id();
-
-    commands.spawn((
-        Enemy,
-        Anchor::CENTER,
-        sprite,
-        Transform::from_translation(enemy_pos.extend(ENEMY_Z)),
-        LerpDestination(lerp_dest),
-        Health::new(4),
-        children![(
-            Healthbar,
-            Mesh2d(meshes.add(healthbar_capsule)),
-            MeshMaterial2d(materials.add(healthbar_color)),
-            healthbar_transform
-        )],
-    ));
-}
-
-#[tweak_fn]
-fn make_healthbar_capsule(ratio: f32) -> Capsule2d {
-    Capsule2d::new(4.0, 32.0 * ratio)
-}
-
-fn despawn_zero_health_enemies(
-    mut commands: Commands,
-    enemies: Query<(Entity, &Health), With<Enemy>>,
-) {
-    for (enemy, health) in &enemies {
-        if health.remaining <= 0 {
-            commands.entity(enemy).despawn();
-        }
-    }
-}
-
-#[tweak_fn]
-fn update_enemy_lerp_dests(
-    on_beat: Res<OnBeat>,
-    enemies: Query<(&Transform, &LerpDestination, Option<&GotHit>), With<Enemy>>,
-    mut destinations: Query<&mut Transform, (With<EnemyLerpDest>, Without<Enemy>)>,
-) {
-    if !on_beat.0 {
-        return;
-    }
-
-    let enemy_lerp_range = 75.0;
-    let enemy_hit_lerp_range = 15.0;
-    for (enemy_transform, lerp_dest, got_hit) in &enemies {
-        let enemy_pos = enemy_transform.translation.xy();
-
-        let dir_to_origin = (-enemy_pos).try_normalize();
-        let Some(dir_to_origin) = dir_to_origin else {
-            continue;
-        };
-
-        let new_dest_pos = if got_hit.is_some() {
-            (-1.0 * dir_to_origin * enemy_hit_lerp_range) + enemy_pos
-        } else {
-            (dir_to_origin * enemy_lerp_range) + enemy_pos
-        };
-
-        let mut lerp_dest_transform = destinations.get_mut(lerp_dest.0).unwrap();
-        lerp_dest_transform.translation.x = new_dest_pos.x;
-        lerp_dest_transform.translation.y = new_dest_pos.y;
-    }
-}
-
-#[tweak_fn]
-fn move_enemies(
-    mut enemies: Query<(&mut Transform, &LerpDestination), With<Enemy>>,
-    destinations: Query<&Transform, (With<EnemyLerpDest>, Without<Enemy>)>,
-) {
-    let enemy_lerp_speed = 0.2;
-
-    for (mut enemy_transform, lerp_dest) in &mut enemies {
-        let enemy_pos = enemy_transform.translation.xy();
-        let dest_transform = destinations.get(lerp_dest.0).unwrap();
-        let dest_pos = dest_transform.translation.xy();
-        let moved = enemy_pos.lerp(dest_pos, enemy_lerp_speed);
-        enemy_transform.translation.x = moved.x;
-        enemy_transform.translation.y = moved.y;
-    }
-}
-
-/// The relation TO a lerp destination
-#[derive(Component)]
-#[relationship(relationship_target = Lerpers)]
-struct LerpDestination(pub Entity);
-
-/// The relation TO all lerpers to a given lerp destination
-#[derive(Component)]
-#[relationship_target(relationship = LerpDestination)]
-struct Lerpers(Vec<Entity>);
-
-fn spawn_main_menu(mut commands: Commands) {
-    commands.spawn((
-        Node {
-            width: Val::Percent(100.0),
-            height: Val::Percent(100.0),
-            align_items: AlignItems::Center,
-            justify_content: JustifyContent::Center,
-            flex_direction: FlexDirection::Column,
-            row_gap: Val::Px(40.0),
-            ..default()
-        },
-        GlobalZIndex(2),
-        DespawnOnExit(Screen::Menu),
-        Pickable::IGNORE,
-        Children::spawn(SpawnWith(|parent: &mut ChildSpawner| {
-            // Title
-            parent.spawn((
-                Text::new("vivace"),
-                TextFont {
-                    font_size: 72.0,
-                    ..default()
-                },
-                TextColor(Color::srgb(0.15, 0.15, 0.15)),
-            ));
-            // Play button
-            parent
-                .spawn((
-                    Button,
-                    Node {
-                        width: Val::Px(200.0),
-                        height: Val::Px(65.0),
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        border: UiRect::all(Val::Px(3.0)),
-                        border_radius: BorderRadius::MAX,
-                        ..default()
-                    },
-                    BorderColor::all(Color::srgb(0.15, 0.15, 0.15)),
-                    BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
-                    children![(
-                        Text::new("Play"),
-                        TextFont {
-                            font_size: 40.0,
-                            ..default()
-                        },
-                        TextColor(Color::srgb(0.9, 0.9, 0.9)),
-                        Pickable::IGNORE,
-                    )],
-                ))
-                .observe(start_game);
-        })),
-    ));
-}
-
-fn start_game(_on_click: On<Pointer<Click>>, mut next_screen: ResMut<NextState<Screen>>) {
-    next_screen.set(Screen::InGame)
-}
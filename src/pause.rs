@@ -0,0 +1,220 @@
+//! Pausing, and the count-in when resuming
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_kira_audio::{AudioInstance, AudioTween};
+
+use crate::beats::Beats;
+use crate::input::{ActionInput, PAUSE_KEY};
+use crate::settings::Settings;
+use crate::songs::CurrentSong;
+use crate::timing::{BeatIndex, TrackInstance, TrackTimer};
+use crate::ui::{menu_button, return_to_menu};
+use crate::{BeatSet, InputSet, Screen};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(OnEnter(Pause::Paused), (pause_track, spawn_pause_menu))
+        .add_systems(
+            OnEnter(Pause::CountIn),
+            (start_count_in, spawn_count_in_text),
+        )
+        .add_systems(OnExit(Screen::InGame), clear_resume_at)
+        .add_systems(
+            Update,
+            (toggle_pause, run_count_in.run_if(in_state(Pause::CountIn)))
+                .chain()
+                .after(BeatSet::Pulse)
+                .before(InputSet)
+                .run_if(in_state(Screen::InGame)),
+        );
+}
+
+/// Whether gameplay is running within [`Screen::InGame`]
+#[derive(SubStates, Default, Debug, Clone, PartialEq, Eq, Hash)]
+#[source(Screen = Screen::InGame)]
+pub(super) enum Pause {
+    #[default]
+    Running,
+    Paused,
+    /// replaying the beats leading up to the pause, before gameplay continues
+    CountIn,
+}
+
+/// beats of music replayed before gameplay continues after a pause
+const COUNT_IN_BEATS: usize = 4;
+
+/// the track time at which gameplay was paused, and will resume
+#[derive(Resource)]
+struct ResumeAt(f32);
+
+fn clear_resume_at(mut commands: Commands) {
+    commands.remove_resource::<ResumeAt>();
+}
+
+pub(super) fn toggle_pause(
+    input: ActionInput,
+    settings: Res<Settings>,
+    pause: Res<State<Pause>>,
+    mut next_pause: ResMut<NextState<Pause>>,
+) {
+    let pressed =
+        input.just_pressed(&settings.bindings.pause) || input.keyboard.just_pressed(PAUSE_KEY);
+    if !pressed && !input.pause_gesture() {
+        return;
+    }
+
+    match pause.get() {
+        Pause::Running | Pause::CountIn => next_pause.set(Pause::Paused),
+        Pause::Paused => next_pause.set(Pause::CountIn),
+    }
+}
+
+fn pause_track(
+    mut commands: Commands,
+    track_instance: Res<TrackInstance>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    track_timer: Res<TrackTimer>,
+    resume_at: Option<Res<ResumeAt>>,
+) {
+    if let Some(instance) = audio_instances.get_mut(&track_instance.handle) {
+        instance.pause(AudioTween::default());
+    }
+
+    // pausing again during a count-in keeps the original resume point
+    let paused_at = track_timer.0.elapsed_secs();
+    let resume_at = resume_at.map_or(paused_at, |resume_at| resume_at.0.max(paused_at));
+    commands.insert_resource(ResumeAt(resume_at));
+}
+
+/// Rewinds the track a few beats before the pause and resumes the music,
+/// so players can re-find the rhythm before gameplay continues.
+fn start_count_in(
+    mut track_instance: ResMut<TrackInstance>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    mut track_timer: ResMut<TrackTimer>,
+    mut beat_index: ResMut<BeatIndex>,
+    resume_at: Res<ResumeAt>,
+    settings: Res<Settings>,
+    song: Res<CurrentSong>,
+    beats_assets: Res<Assets<Beats>>,
+) {
+    let beats = beats_assets.get(&song.beats).unwrap();
+    let resume_beat = beats.beats.partition_point(|&beat| beat < resume_at.0);
+    let count_in_start = resume_beat
+        .checked_sub(COUNT_IN_BEATS)
+        .and_then(|i| beats.beats.get(i))
+        .copied()
+        .unwrap_or(0.0);
+
+    track_timer
+        .0
+        .set_elapsed(Duration::from_secs_f32(count_in_start));
+
+    // the beat clock only counts forwards, so rewind it along with the track
+    let heard_time = settings.heard_track_time(&track_timer);
+    beat_index.0 = beats
+        .beats
+        .partition_point(|&beat| beat <= heard_time)
+        .saturating_sub(1);
+
+    // the reported position lags behind the seek, so don't count that as drift
+    track_instance.desynced_frames = 0;
+
+    if let Some(instance) = audio_instances.get_mut(&track_instance.handle) {
+        instance.seek_to(count_in_start as f64);
+        instance.resume(AudioTween::default());
+    }
+}
+
+#[derive(Component)]
+struct CountInText;
+
+fn spawn_count_in_text(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        GlobalZIndex(2),
+        DespawnOnExit(Pause::CountIn),
+        Pickable::IGNORE,
+        children![(
+            CountInText,
+            Text::new(""),
+            TextFont {
+                font_size: 96.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.15, 0.15, 0.15)),
+        )],
+    ));
+}
+
+fn run_count_in(
+    mut commands: Commands,
+    track_timer: Res<TrackTimer>,
+    resume_at: Res<ResumeAt>,
+    song: Res<CurrentSong>,
+    beats_assets: Res<Assets<Beats>>,
+    mut texts: Query<&mut Text, With<CountInText>>,
+    mut next_pause: ResMut<NextState<Pause>>,
+) {
+    let track_time = track_timer.0.elapsed_secs();
+    if track_time >= resume_at.0 {
+        commands.remove_resource::<ResumeAt>();
+        next_pause.set(Pause::Running);
+        return;
+    }
+
+    let beats = beats_assets.get(&song.beats).unwrap();
+    let beats_left = beats
+        .beats
+        .iter()
+        .filter(|&&beat| beat >= track_time && beat < resume_at.0)
+        .count();
+
+    for mut text in &mut texts {
+        text.0 = if beats_left > 0 {
+            beats_left.to_string()
+        } else {
+            String::new()
+        };
+    }
+}
+
+fn spawn_pause_menu(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(40.0),
+            ..default()
+        },
+        GlobalZIndex(2),
+        DespawnOnExit(Pause::Paused),
+        Pickable::IGNORE,
+        Children::spawn(SpawnWith(|parent: &mut ChildSpawner| {
+            parent.spawn((
+                Text::new("Paused"),
+                TextFont {
+                    font_size: 72.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.15, 0.15, 0.15)),
+            ));
+            parent.spawn(menu_button("Resume")).observe(resume_game);
+            parent.spawn(menu_button("Menu")).observe(return_to_menu);
+        })),
+    ));
+}
+
+fn resume_game(_on_click: On<Pointer<Click>>, mut next_pause: ResMut<NextState<Pause>>) {
+    next_pause.set(Pause::CountIn)
+}
@@ -0,0 +1,286 @@
+//! The quill the player scribbles with, its reticle and the ink it leaves
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use inline_tweak::tweak_fn;
+use rand::Rng;
+
+use crate::assets::{SpriteAtlas, StartupAssetHandles};
+use crate::input::Intent;
+use crate::lerp::{BeatLerp, LerpDestinationOf, LerpSet, SmoothLerp};
+use crate::replay::GameRng;
+use crate::timing::{BeatIndex, MusicalPosition, OnSubdivision, Subdivision, ease_pulse};
+use crate::{BeatSet, CleanupSet, INK_Z, MovementSet, RETICLE_Z, Screen};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(OnEnter(Screen::InGame), spawn_quill)
+        .add_systems(Update, quill_reticle_size_beat.in_set(BeatSet::Pulse))
+        .add_systems(
+            Update,
+            (
+                (aim_quill_reticle, move_quill_target).before(LerpSet),
+                (rotate_quill_sprite, drop_ink_behind_quill)
+                    .chain()
+                    .after(LerpSet),
+            )
+                .in_set(MovementSet),
+        )
+        .add_systems(Update, despawn_old_ink.in_set(CleanupSet));
+}
+
+#[derive(Component)]
+pub(super) struct QuillReticle;
+
+#[derive(Component)]
+struct Quill;
+
+/// where the quill last dropped ink from, kept on the quill so every run starts a fresh stroke
+#[derive(Component, Default)]
+struct InkTrail {
+    last_pos: Option<Vec2>,
+}
+
+#[derive(Component)]
+struct QuillTarget;
+
+/// where the quill reticle is heading: wherever the player is aiming
+#[derive(Component)]
+struct QuillAim;
+
+const RETICLE_BIG_INNER_RADIUS: f32 = 30.0;
+const RETICLE_BIG_OUTER_RADIUS: f32 = 40.0;
+
+/// seconds for the reticle to close half the distance to where the player is aiming
+const RETICLE_HALF_LIFE: f32 = 0.05;
+
+fn spawn_quill(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    asset_handles: Res<StartupAssetHandles>,
+    atlases: Res<Assets<SpriteAtlas>>,
+) {
+    let mesh = meshes.add(make_reticle(1.0));
+    let color = make_reticle_color(1.0);
+    let translation = Vec3::new(0.0, 0.0, RETICLE_Z);
+
+    let atlas = atlases.get(&asset_handles.sprite_atlas).unwrap();
+    let offsets = atlas.get_offsets_or_panic("quill");
+
+    let sprite = Sprite {
+        image: asset_handles.sprite_sheet.clone(),
+        rect: Some(offsets.as_rect()),
+        ..default()
+    };
+
+    let reticle = commands
+        .spawn((
+            QuillReticle,
+            DespawnOnExit(Screen::InGame),
+            Mesh2d(mesh),
+            MeshMaterial2d(materials.add(color)),
+            Transform::from_translation(translation),
+            Visibility::default(),
+            SmoothLerp {
+                half_life: RETICLE_HALF_LIFE,
+            },
+        ))
+        .with_children(|reticle| {
+            let quill = reticle
+                .spawn((
+                    Quill,
+                    InkTrail::default(),
+                    Anchor::BOTTOM_LEFT,
+                    sprite,
+                    Transform::default(),
+                    BeatLerp::new(Subdivision::Eighth, EaseFunction::CubicOut, 0.75),
+                ))
+                .id();
+            reticle.spawn((QuillTarget, Transform::default(), LerpDestinationOf(quill)));
+        })
+        .id();
+
+    commands.spawn((QuillAim, Transform::default(), LerpDestinationOf(reticle)));
+}
+
+#[tweak_fn]
+fn make_reticle(ratio: f32) -> Annulus {
+    Annulus::new(
+        ratio * RETICLE_BIG_INNER_RADIUS,
+        ratio * RETICLE_BIG_OUTER_RADIUS,
+    )
+}
+
+#[tweak_fn]
+fn make_reticle_color(ratio: f32) -> Color {
+    let lightness = (1.0 - ratio) * 0.5 + 0.2;
+    Color::hsl(200.0, 0.95, lightness)
+}
+
+#[tweak_fn]
+fn quill_reticle_size_beat(
+    mut reticles: Query<(&mut Mesh2d, &mut MeshMaterial2d<ColorMaterial>), With<QuillReticle>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    position: Res<MusicalPosition>,
+) {
+    // smallest on the beat, and faster on the way down
+    let wave = ease_pulse(
+        position.fraction,
+        0.6,
+        EaseFunction::SineOut,
+        EaseFunction::QuadraticIn,
+    );
+    let ratio = 0.25 + 0.25 * wave;
+
+    // lighter through the bar, so downbeats stand out
+    let bar_accent = EaseFunction::QuadraticOut.sample_clamped(position.bar_phase());
+
+    let shape = make_reticle(ratio);
+    let color = make_reticle_color(ratio + 0.3 * (1.0 - bar_accent));
+    for (mut mesh, mut material) in &mut reticles {
+        mesh.0 = meshes.add(shape);
+        material.0 = materials.add(color);
+    }
+}
+
+fn aim_quill_reticle(intent: Res<Intent>, mut aims: Query<&mut Transform, With<QuillAim>>) {
+    for mut transform in &mut aims {
+        transform.translation = intent.aim_pos.extend(0.0);
+    }
+}
+
+pub(super) const SCRIBBLE_HORIZONTAL_RANGE: f32 = 100.0;
+
+#[tweak_fn]
+fn move_quill_target(
+    intent: Res<Intent>,
+    position: Res<MusicalPosition>,
+    mut rng: ResMut<GameRng>,
+    mut on_subdivision: MessageReader<OnSubdivision>,
+    mut quill_targets: Query<&mut Transform, With<QuillTarget>>,
+) {
+    let mut target_transform = quill_targets.single_mut().unwrap();
+
+    let eighth = on_subdivision
+        .read()
+        .filter(|tick| tick.subdivision == Subdivision::Eighth)
+        .last();
+
+    if intent.quill_down {
+        // every bar starts with a stroke to the right
+        let even_beat = position.beat_in_bar % 2 == 0;
+        let x_dir = if even_beat { 1.0 } else { -1.0 };
+        let x = x_dir * SCRIBBLE_HORIZONTAL_RANGE;
+
+        // zig-zag up on the beat and down on the off-beat
+        let y = if let Some(eighth) = eighth {
+            let vertical_range = 30.0;
+            let y_dir = if eighth.is_off_beat() { -1.0 } else { 1.0 };
+            y_dir * rng.visual.random_range(0.0..vertical_range)
+        } else {
+            target_transform.translation.y
+        };
+
+        target_transform.translation = Vec3::new(x, y, 0.0);
+    } else {
+        target_transform.translation = Vec3::ZERO;
+    }
+}
+
+#[tweak_fn]
+fn rotate_quill_sprite(mut quills: Query<&mut Transform, With<Quill>>) {
+    let mut quill_transform = quills.single_mut().unwrap();
+
+    let x_neg_one_to_one = quill_transform.translation.x / SCRIBBLE_HORIZONTAL_RANGE;
+    let mut angle_radians = x_neg_one_to_one * -1.0;
+    let angle_modifier = if angle_radians < 0.0 { 0.25 } else { 0.75 };
+    angle_radians *= angle_modifier;
+
+    quill_transform.rotation = Quat::from_rotation_z(angle_radians);
+}
+
+#[derive(Component)]
+struct Ink {
+    spawn_beat: usize,
+}
+
+#[tweak_fn]
+pub(super) fn make_ink_color() -> Color {
+    make_reticle_color(0.75)
+}
+
+#[tweak_fn]
+fn drop_ink_behind_quill(
+    beat_index: Res<BeatIndex>,
+    mut quills: Query<(&GlobalTransform, &mut InkTrail), With<Quill>>,
+    intent: Res<Intent>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut commands: Commands,
+) {
+    let (quill_transform, mut ink_trail) = quills.single_mut().unwrap();
+    let quill_position = quill_transform.translation();
+
+    match ink_trail.last_pos {
+        None => {
+            ink_trail.last_pos = Some(quill_position.xy());
+        }
+
+        Some(last_quill_pos) => {
+            let quill_pos = quill_position.xy();
+            let capsule_rotation = {
+                match (quill_pos - last_quill_pos).try_normalize() {
+                    None => Quat::default(),
+                    Some(mouse_direction) => {
+                        let capsule_angle = Vec2::Y.angle_to(mouse_direction);
+                        Quat::from_rotation_z(capsule_angle)
+                    }
+                }
+            };
+
+            let capsule_pos = quill_pos.midpoint(last_quill_pos);
+            let capsule_transform = Transform {
+                translation: capsule_pos.extend(INK_Z),
+                rotation: capsule_rotation,
+                ..default()
+            };
+
+            let capsule_len = quill_pos.distance(last_quill_pos).abs();
+            let capsule_radius = 5.0;
+            let capsule = Capsule2d::new(capsule_radius, capsule_len);
+
+            ink_trail.last_pos = Some(quill_pos);
+            if !intent.quill_down {
+                return;
+            }
+
+            let spawn_beat = beat_index.0;
+            let mesh = meshes.add(capsule);
+            let color = make_ink_color();
+
+            commands.spawn((
+                Ink { spawn_beat },
+                DespawnOnExit(Screen::InGame),
+                Mesh2d(mesh.clone()),
+                MeshMaterial2d(materials.add(color)),
+                capsule_transform,
+            ));
+        }
+    }
+}
+
+#[tweak_fn]
+fn despawn_old_ink(
+    beat_index: Res<BeatIndex>,
+    inks: Query<(Entity, &Ink)>,
+    mut commands: Commands,
+) {
+    for (entity, ink) in &inks {
+        let age_beats = beat_index.0 - ink.spawn_beat;
+        let despawn_after_beats = 2;
+        if age_beats > despawn_after_beats {
+            commands.entity(entity).despawn();
+        }
+    }
+}
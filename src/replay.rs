@@ -0,0 +1,380 @@
+//! Seeded randomness, and recording runs to replay files
+
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::input::{Intent, read_input};
+use crate::settings::Settings;
+use crate::songs::{CurrentSong, SongDestination};
+use crate::timing::TrackTimer;
+use crate::{InputSet, Screen};
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<GameRng>()
+        .init_resource::<SeedArg>()
+        .init_resource::<DailyChallenge>()
+        .init_resource::<Recording>()
+        .add_systems(Startup, (load_seed_arg, load_replay_arg))
+        .add_systems(OnEnter(Screen::InGame), (seed_rng, start_recording).chain())
+        .add_systems(OnExit(Screen::InGame), (save_recording, finish_replay))
+        .add_systems(
+            OnEnter(Screen::Menu),
+            start_pending_replay.run_if(resource_exists::<PendingReplay>),
+        )
+        .add_systems(
+            Update,
+            (
+                replay_input.run_if(resource_exists::<ReplayPlayback>),
+                record_input.run_if(not(resource_exists::<ReplayPlayback>)),
+            )
+                .chain()
+                .in_set(InputSet)
+                .after(read_input),
+        );
+}
+
+/// The source of all gameplay randomness, reseeded each run so runs can be replayed
+///
+/// Cosmetic randomness draws from its own stream, so it can change without changing gameplay.
+#[derive(Resource)]
+pub(super) struct GameRng {
+    pub(super) seed: u64,
+    /// for anything that affects gameplay, like enemy spawns
+    pub(super) spawn: StdRng,
+    /// for visual jitter only
+    pub(super) visual: StdRng,
+}
+
+/// mixed into the seed for the visual stream, to keep it independent of the spawn stream
+const VISUAL_STREAM: u64 = 0x9e37_79b9_7f4a_7c15;
+
+impl GameRng {
+    pub(super) fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            spawn: StdRng::seed_from_u64(seed),
+            visual: StdRng::seed_from_u64(seed ^ VISUAL_STREAM),
+        }
+    }
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// a fixed seed for every run, from `--seed <n>`
+#[derive(Resource, Default)]
+pub(super) struct SeedArg(Option<u64>);
+
+/// whether the current run is the daily challenge, which everyone plays with the same seed
+#[derive(Resource, Default)]
+pub(super) struct DailyChallenge(pub(super) bool);
+
+/// mixed into the day when deriving the daily seed, so it doesn't match any small `--seed`
+const DAILY_SEED_SALT: u64 = 0x6461_696c_7920_7669;
+
+/// the seed for today's daily challenge, which changes at midnight UTC
+fn daily_seed() -> u64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as f64;
+    #[cfg(target_arch = "wasm32")]
+    let millis = js_sys::Date::now();
+
+    let day = (millis / (24.0 * 60.0 * 60.0 * 1000.0)) as u64;
+    mix_seed(day ^ DAILY_SEED_SALT)
+}
+
+/// Scrambles a number so nearby inputs give unrelated outputs (the SplitMix64 finalizer)
+///
+/// Spelled out rather than using std's hasher, whose output may change between Rust releases.
+fn mix_seed(value: u64) -> u64 {
+    let mut x = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Picks this run's seed: a replay's seed, the daily seed, the command line seed, or a fresh one
+pub(super) fn seed_rng(
+    playback: Option<Res<ReplayPlayback>>,
+    daily: Res<DailyChallenge>,
+    seed_arg: Res<SeedArg>,
+    mut rng: ResMut<GameRng>,
+) {
+    let seed = if let Some(playback) = playback {
+        playback.replay.seed
+    } else if daily.0 {
+        daily_seed()
+    } else {
+        seed_arg.0.unwrap_or_else(rand::random)
+    };
+
+    log::info!("seed: {seed}");
+    *rng = GameRng::new(seed);
+}
+
+/// the value following `name` on the command line
+fn arg_value(name: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != name).nth(1)
+}
+
+fn load_seed_arg(mut seed_arg: ResMut<SeedArg>) {
+    let Some(seed) = arg_value("--seed") else {
+        return;
+    };
+
+    match seed.parse() {
+        Ok(seed) => seed_arg.0 = Some(seed),
+        Err(e) => log::error!("invalid seed {seed}: {e}"),
+    }
+}
+
+/// where finished runs are saved, relative to the working directory
+const REPLAY_DIR: &str = "replays";
+
+/// A recorded run: everything needed to play it back the same way
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+pub(super) struct Replay {
+    seed: u64,
+    /// asset paths of the song
+    audio: String,
+    beats: String,
+    chart: Option<String>,
+    /// the latency offsets the run was played with
+    audio_offset: f32,
+    input_offset: f32,
+    /// sorted by track time
+    frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    pub(super) fn from_json(json: &str) -> Result<Self, String> {
+        let replay: Self = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        replay.check_frames()?;
+        Ok(replay)
+    }
+
+    /// playback searches the frames by track time, so they can't be out of order
+    fn check_frames(&self) -> Result<(), String> {
+        if let Some(frame) = self.frames.iter().find(|f| !f.track_time.is_finite()) {
+            return Err(format!("invalid track time: {}", frame.track_time));
+        }
+
+        match self
+            .frames
+            .windows(2)
+            .find(|pair| pair[1].track_time < pair[0].track_time)
+        {
+            Some(pair) => Err(format!(
+                "frames out of order: {} after {}",
+                pair[1].track_time, pair[0].track_time
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// the player's intent as of a point in the track
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+struct ReplayFrame {
+    track_time: f32,
+    aim_pos: Vec2,
+    quill_down: bool,
+    quill_pressed: bool,
+}
+
+/// the run in progress, saved to [`REPLAY_DIR`] when it ends
+#[derive(Resource, Default)]
+pub(super) struct Recording(pub(super) Replay);
+
+/// a replay loaded from the command line, which plays once the menu is reached
+#[derive(Resource)]
+struct PendingReplay(Replay);
+
+/// Feeds a replay's recorded intents into gameplay in place of player input
+#[derive(Resource)]
+pub(super) struct ReplayPlayback {
+    pub(super) replay: Replay,
+    /// the track time input was last read at, so presses in between aren't missed
+    pub(super) last_track_time: f32,
+    /// the player's own offsets, restored once the replay ends
+    pub(super) player_offsets: (f32, f32),
+}
+
+/// loads the replay passed with `--replay <path>`, or leaves input live if it can't
+fn load_replay_arg(mut commands: Commands) {
+    let Some(path) = arg_value("--replay") else {
+        return;
+    };
+
+    let replay = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|json| Replay::from_json(&json));
+
+    match replay {
+        Ok(replay) => commands.insert_resource(PendingReplay(replay)),
+        Err(e) => log::error!("failed to load replay {path}: {e}"),
+    }
+}
+
+fn start_pending_replay(
+    mut commands: Commands,
+    pending: Res<PendingReplay>,
+    asset_server: Res<AssetServer>,
+    mut settings: ResMut<Settings>,
+    mut destination: ResMut<SongDestination>,
+    mut next_screen: ResMut<NextState<Screen>>,
+) {
+    let replay = pending.0.clone();
+    commands.remove_resource::<PendingReplay>();
+
+    commands.insert_resource(CurrentSong {
+        audio: asset_server.load(&replay.audio),
+        beats: asset_server.load(&replay.beats),
+        chart: replay.chart.as_ref().map(|chart| asset_server.load(chart)),
+    });
+
+    let player_offsets = (settings.audio_offset, settings.input_offset);
+    settings.audio_offset = replay.audio_offset;
+    settings.input_offset = replay.input_offset;
+
+    commands.insert_resource(ReplayPlayback {
+        replay,
+        last_track_time: 0.0,
+        player_offsets,
+    });
+
+    destination.0 = Screen::InGame;
+    next_screen.set(Screen::SongLoading);
+}
+
+pub(super) fn replay_input(
+    track_timer: Res<TrackTimer>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut intent: ResMut<Intent>,
+) {
+    let now = track_timer.0.elapsed_secs();
+    let frames = &playback.replay.frames;
+
+    let applied = frames.partition_point(|frame| frame.track_time <= now);
+    let Some(frame) = applied.checked_sub(1).map(|i| frames[i]) else {
+        return;
+    };
+
+    // a rewind (like a count-in) doesn't replay presses
+    let since = playback.last_track_time;
+    let press = frames[..applied]
+        .iter()
+        .rev()
+        .take_while(|frame| frame.track_time > since)
+        .find(|frame| frame.quill_pressed);
+
+    intent.aim_pos = frame.aim_pos;
+    // a tap shorter than this frame still needs to be down to land
+    intent.quill_down = frame.quill_down || press.is_some();
+    intent.quill_pressed = press.is_some();
+    // judged when it was recorded, not whenever this frame happens to land
+    intent.press_time = press.map(|frame| frame.track_time);
+    playback.last_track_time = now;
+}
+
+pub(super) fn record_input(
+    track_timer: Res<TrackTimer>,
+    intent: Res<Intent>,
+    mut recording: ResMut<Recording>,
+) {
+    let frame = ReplayFrame {
+        track_time: track_timer.0.elapsed_secs(),
+        aim_pos: intent.aim_pos,
+        quill_down: intent.quill_down,
+        quill_pressed: intent.quill_pressed,
+    };
+
+    let frames = &mut recording.0.frames;
+    if let Some(last) = frames.last() {
+        // only keep changes, and stay sorted after a count-in rewinds the track
+        let unchanged = ReplayFrame {
+            track_time: last.track_time,
+            ..frame
+        } == *last;
+        if unchanged || frame.track_time <= last.track_time {
+            return;
+        }
+    }
+
+    frames.push(frame);
+}
+
+fn start_recording(
+    song: Res<CurrentSong>,
+    settings: Res<Settings>,
+    rng: Res<GameRng>,
+    mut recording: ResMut<Recording>,
+) {
+    recording.0 = Replay {
+        seed: rng.seed,
+        audio: asset_path(&song.audio),
+        beats: asset_path(&song.beats),
+        chart: song.chart.as_ref().map(asset_path),
+        audio_offset: settings.audio_offset,
+        input_offset: settings.input_offset,
+        frames: Vec::new(),
+    };
+}
+
+fn asset_path<A: Asset>(handle: &Handle<A>) -> String {
+    handle
+        .path()
+        .map(|path| path.to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_recording(recording: Res<Recording>, playback: Option<Res<ReplayPlayback>>) {
+    if playback.is_some() || recording.0.frames.is_empty() {
+        return;
+    }
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = format!("{REPLAY_DIR}/{timestamp}.replay.json");
+
+    let written = std::fs::create_dir_all(REPLAY_DIR)
+        .map_err(|e| e.to_string())
+        .and_then(|()| serde_json::to_string(&recording.0).map_err(|e| e.to_string()))
+        .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+
+    match written {
+        Ok(()) => log::info!("saved replay to {path}"),
+        Err(e) => log::warn!("failed to save replay to {path}: {e}"),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn save_recording(recording: Res<Recording>) {
+    if !recording.0.frames.is_empty() {
+        log::warn!("replays aren't saved on web");
+    }
+}
+
+/// returns to normal play after a replay
+fn finish_replay(
+    mut commands: Commands,
+    playback: Option<Res<ReplayPlayback>>,
+    mut settings: ResMut<Settings>,
+) {
+    let Some(playback) = playback else {
+        return;
+    };
+
+    (settings.audio_offset, settings.input_offset) = playback.player_offsets;
+    commands.remove_resource::<ReplayPlayback>();
+}
@@ -0,0 +1,202 @@
+//! Score, combo and the in-game hud
+
+use bevy::prelude::*;
+
+use crate::combat::{Damage, Died, Judgement, ScribbleJudged, judge_scribbles};
+use crate::enemies::Enemy;
+use crate::flag::Flag;
+use crate::replay::{DailyChallenge, GameRng, seed_rng};
+use crate::{CombatSet, Screen, UiSet};
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<Score>()
+        .add_systems(
+            OnEnter(Screen::InGame),
+            (reset_score, spawn_score_hud.after(seed_rng)),
+        )
+        .add_systems(
+            Update,
+            (
+                count_judgements
+                    .in_set(CombatSet::Judge)
+                    .after(judge_scribbles),
+                update_score_hud.in_set(UiSet),
+            ),
+        )
+        .add_observer(score_damage)
+        .add_observer(score_defeat);
+}
+
+/// points for defeating an enemy, before the multiplier
+const DEFEAT_POINTS: u32 = 500;
+
+/// the minimum combo for each multiplier
+const MULTIPLIER_LADDER: [(u32, u32); 4] = [(0, 1), (8, 2), (16, 3), (32, 4)];
+
+#[derive(Resource, Default, Debug)]
+pub(super) struct Score {
+    pub(super) points: u32,
+    /// consecutive judged scribbles that landed on an enemy without missing
+    pub(super) combo: u32,
+    pub(super) max_combo: u32,
+    pub(super) enemies_defeated: u32,
+    /// every judged scribble this run, hit or not
+    pub(super) judgements: Vec<Judgement>,
+    /// the hit circle that last added to the combo, so a scribble counts once however many it hits
+    combo_source: Option<Entity>,
+}
+
+impl Score {
+    fn multiplier(&self) -> u32 {
+        MULTIPLIER_LADDER
+            .iter()
+            .rev()
+            .find(|(min_combo, _)| self.combo >= *min_combo)
+            .map(|(_, multiplier)| *multiplier)
+            .unwrap_or(1)
+    }
+
+    /// counts a scribble that landed on at least one enemy towards the combo
+    fn add_hit(&mut self, judgement: Judgement) {
+        // misses already broke the combo as they were judged
+        if judgement == Judgement::Miss {
+            return;
+        }
+
+        self.combo += 1;
+        self.max_combo = self.max_combo.max(self.combo);
+    }
+
+    fn add_damage(&mut self, judgement: Judgement) {
+        self.points += judgement.points() * self.multiplier();
+    }
+
+    fn add_defeat(&mut self) {
+        self.enemies_defeated += 1;
+        self.points += DEFEAT_POINTS * self.multiplier();
+    }
+
+    /// 0.0-1.0
+    pub(super) fn accuracy(&self) -> f32 {
+        if self.judgements.is_empty() {
+            return 0.0;
+        }
+
+        let total: f32 = self.judgements.iter().map(|j| j.accuracy()).sum();
+        total / self.judgements.len() as f32
+    }
+
+    pub(super) fn count(&self, judgement: Judgement) -> usize {
+        self.judgements.iter().filter(|&&j| j == judgement).count()
+    }
+
+    fn break_combo(&mut self) {
+        self.combo = 0;
+    }
+}
+
+fn reset_score(mut score: ResMut<Score>) {
+    *score = Score::default();
+}
+
+/// scores damage to enemies and counts the scribbles that dealt it towards the combo,
+/// and breaks the combo when the flag is hit
+fn score_damage(damage: On<Damage>, flags: Query<(), With<Flag>>, mut score: ResMut<Score>) {
+    if flags.contains(damage.target) {
+        score.break_combo();
+        return;
+    }
+
+    let Some(judgement) = damage.judgement else {
+        return;
+    };
+
+    if score.combo_source != Some(damage.source) {
+        score.combo_source = Some(damage.source);
+        score.add_hit(judgement);
+    }
+    score.add_damage(judgement);
+}
+
+fn score_defeat(died: On<Died>, enemies: Query<(), With<Enemy>>, mut score: ResMut<Score>) {
+    if enemies.contains(died.entity) {
+        score.add_defeat();
+    }
+}
+
+fn count_judgements(mut judged_scribbles: MessageReader<ScribbleJudged>, mut score: ResMut<Score>) {
+    for judged in judged_scribbles.read() {
+        score.judgements.push(judged.judgement);
+
+        // missing the beat breaks the combo, even with nothing under the quill
+        if judged.judgement == Judgement::Miss {
+            score.break_combo();
+        }
+    }
+}
+
+#[derive(Component)]
+struct ScoreText;
+
+#[derive(Component)]
+struct ComboText;
+
+fn spawn_score_hud(mut commands: Commands, rng: Res<GameRng>, daily: Res<DailyChallenge>) {
+    let text_font = TextFont {
+        font_size: 28.0,
+        ..default()
+    };
+    let text_color = TextColor(Color::srgb(0.15, 0.15, 0.15));
+
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(16.0),
+            left: Val::Px(16.0),
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(4.0),
+            ..default()
+        },
+        GlobalZIndex(1),
+        DespawnOnExit(Screen::InGame),
+        Pickable::IGNORE,
+        children![
+            (ScoreText, Text::new("0"), text_font.clone(), text_color),
+            (ComboText, Text::new(""), text_font, text_color),
+            (
+                Text::new(seed_label(rng.seed, daily.0)),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                text_color,
+            ),
+        ],
+    ));
+}
+
+pub(super) fn seed_label(seed: u64, daily: bool) -> String {
+    if daily {
+        format!("daily challenge (seed {seed})")
+    } else {
+        format!("seed {seed}")
+    }
+}
+
+fn update_score_hud(
+    score: Res<Score>,
+    mut score_texts: Query<&mut Text, (With<ScoreText>, Without<ComboText>)>,
+    mut combo_texts: Query<&mut Text, (With<ComboText>, Without<ScoreText>)>,
+) {
+    if !score.is_changed() {
+        return;
+    }
+
+    for mut text in &mut score_texts {
+        text.0 = score.points.to_string();
+    }
+
+    for mut text in &mut combo_texts {
+        text.0 = format!("{} combo  x{}", score.combo, score.multiplier());
+    }
+}
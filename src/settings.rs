@@ -0,0 +1,87 @@
+//! Per-player preferences, persisted between sessions
+
+use bevy::prelude::*;
+
+use crate::input::Bindings;
+use crate::timing::TrackTimer;
+
+pub(super) fn plugin(app: &mut App) {
+    app.insert_resource(Settings::load());
+}
+
+const SETTINGS_PATH: &str = "settings.json";
+#[cfg(target_arch = "wasm32")]
+const SETTINGS_STORAGE_KEY: &str = "vivace_settings";
+
+/// Per-player preferences, persisted between sessions
+#[derive(Resource, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(super) struct Settings {
+    /// seconds between the audio clock and the music reaching the player's ears
+    pub(super) audio_offset: f32,
+    /// seconds between the player reacting and their input reaching the game
+    pub(super) input_offset: f32,
+    pub(super) bindings: Bindings,
+}
+
+impl Settings {
+    /// the position in the track that the player is currently hearing
+    pub(super) fn heard_track_time(&self, track_timer: &TrackTimer) -> f32 {
+        track_timer.0.elapsed_secs() - self.audio_offset
+    }
+
+    pub(super) fn load() -> Self {
+        let Some(json) = read_settings_json() else {
+            return Self::default();
+        };
+
+        serde_json::from_str(&json).unwrap_or_else(|e| {
+            log::warn!("ignoring invalid settings: {e}");
+            Self::default()
+        })
+    }
+
+    pub(super) fn save(&self) {
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => write_settings_json(&json),
+            Err(e) => log::warn!("failed to serialize settings: {e}"),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_settings_json() -> Option<String> {
+    std::fs::read_to_string(SETTINGS_PATH).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_settings_json(json: &str) {
+    if let Err(e) = std::fs::write(SETTINGS_PATH, json) {
+        log::warn!("failed to write {SETTINGS_PATH}: {e}");
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_settings_json() -> Option<String> {
+    local_storage()?
+        .get_item(SETTINGS_STORAGE_KEY)
+        .ok()
+        .flatten()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_settings_json(json: &str) {
+    let Some(storage) = local_storage() else {
+        log::warn!("local storage unavailable; {SETTINGS_PATH} not saved");
+        return;
+    };
+
+    if storage.set_item(SETTINGS_STORAGE_KEY, json).is_err() {
+        log::warn!("failed to write {SETTINGS_PATH} to local storage");
+    }
+}
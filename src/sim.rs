@@ -0,0 +1,712 @@
+//! Headless gameplay simulation for tests
+//!
+//! Runs the gameplay plugins on [`MinimalPlugins`], with no window, audio device or GPU.
+//! There is no music playing, so musical time only moves when a test sets it.
+
+use std::time::Duration;
+
+use bevy::ecs::system::RunSystemOnce;
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use bevy::time::TimeUpdateStrategy;
+
+use crate::assets::{SpriteAtlas, StartupAssetHandles};
+use crate::beats::{Beats, TimeSignature};
+use crate::chart::{Chart, ChartEvent, ChartEventKind, ChartIssue, SpawnPoint};
+use crate::combat::Judgement;
+use crate::enemies::{Enemy, EnemyKind, spawn_enemy};
+use crate::flag::Flag;
+use crate::health::{Health, HealthbarFill};
+use crate::input::{Action, Binding, Intent};
+use crate::lerp::{BeatLerp, LerpDestination, LerpDestinationOf, LerpSet, SmoothLerp};
+use crate::pause::{Pause, toggle_pause};
+use crate::quill::QuillReticle;
+use crate::replay::{
+    DailyChallenge, GameRng, Recording, Replay, ReplayPlayback, record_input, replay_input,
+};
+use crate::score::Score;
+use crate::settings::Settings;
+use crate::songs::CurrentSong;
+use crate::timing::{BeatIndex, MusicalPosition, OnBeat, Subdivision, TrackTimer};
+use crate::{
+    InputSet, MovementSet, RETICLE_Z, Screen, combat, enemies, flag, gameplay_plugin, health, lerp,
+    score, timing,
+};
+
+/// simulated frame length
+const FRAME: Duration = Duration::from_nanos(16_666_667);
+
+struct Sim {
+    app: App,
+}
+
+impl Sim {
+    /// a song at a steady tempo, with the flag at the origin
+    fn new(bpm: f32, seed: u64) -> Self {
+        let interval = 60.0 / bpm;
+        let beats: Vec<f32> = (0..64).map(|i| i as f32 * interval).collect();
+        let beats = Beats {
+            bpm,
+            beats_confidence: 1.0,
+            beats_intervals: vec![interval; beats.len() - 1],
+            beats,
+            time_signature: TimeSignature::default(),
+            bar_offset: 0,
+            downbeats: Vec::new(),
+        };
+        let track_length = Duration::from_secs_f32(beats.beat_time(beats.beats.len()));
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(FRAME))
+            .init_state::<Screen>()
+            .init_resource::<Assets<Beats>>()
+            .init_resource::<Assets<Chart>>()
+            .init_resource::<Assets<SpriteAtlas>>()
+            .init_resource::<Assets<Mesh>>()
+            .init_resource::<Assets<ColorMaterial>>()
+            .add_plugins((
+                gameplay_plugin,
+                timing::plugin,
+                combat::plugin,
+                enemies::plugin,
+                flag::plugin,
+                health::plugin,
+                score::plugin,
+                lerp::plugin,
+            ))
+            .init_resource::<Intent>()
+            .init_resource::<Settings>()
+            .init_resource::<DailyChallenge>()
+            .insert_resource(TrackTimer::new(track_length))
+            .insert_resource(GameRng::new(seed));
+
+        let world = app.world_mut();
+        let beats = world.resource_mut::<Assets<Beats>>().add(beats);
+        let atlas: SpriteAtlas =
+            serde_json::from_str(include_str!("../assets/sprites/sprite_sheet.atlas.json"))
+                .unwrap();
+        let sprite_atlas = world.resource_mut::<Assets<SpriteAtlas>>().add(atlas);
+
+        world.insert_resource(CurrentSong {
+            audio: Handle::default(),
+            beats,
+            chart: None,
+        });
+        world.insert_resource(StartupAssetHandles {
+            eroica_score: Handle::default(),
+            song_library: Handle::default(),
+            metronome_click: Handle::default(),
+            sprite_sheet: Handle::default(),
+            sprite_atlas,
+        });
+        world.spawn((QuillReticle, Transform::default()));
+
+        // spawns the flag on entering the game
+        world
+            .resource_mut::<NextState<Screen>>()
+            .set(Screen::InGame);
+        app.update();
+
+        Self { app }
+    }
+
+    /// jumps the music to the given beat, and runs a frame
+    fn advance_to_beat(&mut self, beat: usize) {
+        self.advance_to(beat as f32);
+    }
+
+    /// jumps the music partway through a beat, and runs a frame
+    fn advance_to(&mut self, beat_position: f32) {
+        let song = self.app.world().resource::<CurrentSong>();
+        let beats = self.app.world().resource::<Assets<Beats>>();
+        let beats = beats.get(&song.beats).unwrap();
+
+        let beat = beat_position.floor() as usize;
+        let start = beats.beat_time(beat);
+        let end = beats.beat_time(beat + 1);
+        let beat_time = start + (end - start) * beat_position.fract();
+
+        self.app
+            .world_mut()
+            .resource_mut::<TrackTimer>()
+            .0
+            .set_elapsed(Duration::from_secs_f32(beat_time));
+        self.app.update();
+    }
+
+    /// plays the music in real time, at the given frames per second
+    fn play(&mut self, seconds: f32, frame_rate: f32) {
+        let frame = Duration::from_secs_f32(1.0 / frame_rate);
+        self.app
+            .insert_resource(TimeUpdateStrategy::ManualDuration(frame));
+
+        let frames = (seconds * frame_rate).round() as usize;
+        for _ in 0..frames {
+            self.app
+                .world_mut()
+                .resource_mut::<TrackTimer>()
+                .0
+                .tick(frame);
+            self.app.update();
+        }
+    }
+
+    /// runs frames without moving the music
+    fn step_frames(&mut self, frames: usize) {
+        for _ in 0..frames {
+            self.app.update();
+        }
+    }
+
+    /// like [`Sim::play`], starting a scribble on the first frame at or after each track time
+    fn play_scribbling(&mut self, seconds: f32, frame_rate: f32, press_times: &[f32]) {
+        let frame = Duration::from_secs_f32(1.0 / frame_rate);
+        self.app
+            .insert_resource(TimeUpdateStrategy::ManualDuration(frame));
+
+        let mut press_times = press_times.iter().peekable();
+        let frames = (seconds * frame_rate).round() as usize;
+        for _ in 0..frames {
+            let world = self.app.world_mut();
+            let mut track_timer = world.resource_mut::<TrackTimer>();
+            track_timer.0.tick(frame);
+            let now = track_timer.0.elapsed_secs();
+
+            let pressed = press_times.next_if(|&&time| time <= now).is_some();
+            if pressed {
+                *world.resource_mut::<Intent>() = Intent {
+                    quill_down: true,
+                    quill_pressed: true,
+                    ..default()
+                };
+            }
+            self.app.update();
+
+            if pressed {
+                *self.app.world_mut().resource_mut::<Intent>() = Intent::default();
+            }
+        }
+    }
+
+    fn move_reticle(&mut self, pos: Vec2) {
+        let world = self.app.world_mut();
+        let mut reticles = world.query_filtered::<&mut Transform, With<QuillReticle>>();
+        reticles.single_mut(world).unwrap().translation = pos.extend(RETICLE_Z);
+    }
+
+    /// starts a scribble at the given position for one frame
+    fn scribble_at(&mut self, pos: Vec2) {
+        self.move_reticle(pos);
+
+        *self.app.world_mut().resource_mut::<Intent>() = Intent {
+            aim_pos: pos,
+            quill_down: true,
+            quill_pressed: true,
+            ..default()
+        };
+        self.app.update();
+
+        *self.app.world_mut().resource_mut::<Intent>() = Intent::default();
+    }
+
+    fn spawn_enemy(&mut self, pos: Vec2) -> Entity {
+        self.app
+            .world_mut()
+            .run_system_once(
+                move |mut commands: Commands,
+                      asset_handles: Res<StartupAssetHandles>,
+                      atlases: Res<Assets<SpriteAtlas>>| {
+                    let atlas = atlases.get(&asset_handles.sprite_atlas).unwrap();
+                    spawn_enemy(
+                        &mut commands,
+                        &asset_handles,
+                        atlas,
+                        EnemyKind::Soldier,
+                        pos,
+                    );
+                },
+            )
+            .unwrap();
+
+        self.enemies().last().unwrap().0
+    }
+
+    fn enemies(&mut self) -> Vec<(Entity, Vec2)> {
+        let world = self.app.world_mut();
+        let mut enemies = world.query_filtered::<(Entity, &Transform), With<Enemy>>();
+        let mut enemies: Vec<_> = enemies
+            .iter(world)
+            .map(|(enemy, transform)| (enemy, transform.translation.xy()))
+            .collect();
+        enemies.sort_by_key(|(enemy, _)| *enemy);
+        enemies
+    }
+
+    fn enemy_health(&self, enemy: Entity) -> i32 {
+        self.app.world().get::<Health>(enemy).unwrap().remaining
+    }
+
+    fn flag_health(&mut self) -> i32 {
+        let world = self.app.world_mut();
+        let mut flags = world.query_filtered::<&Health, With<Flag>>();
+        flags.single(world).unwrap().remaining
+    }
+}
+
+#[test]
+fn beat_index_follows_musical_time() {
+    let mut sim = Sim::new(120.0, 0);
+
+    sim.advance_to_beat(3);
+    assert_eq!(sim.app.world().resource::<BeatIndex>().0, 3);
+    assert!(sim.app.world().resource::<OnBeat>().0);
+
+    sim.step_frames(1);
+    assert_eq!(sim.app.world().resource::<BeatIndex>().0, 3);
+    assert!(!sim.app.world().resource::<OnBeat>().0);
+
+    let position = *sim.app.world().resource::<MusicalPosition>();
+    assert_eq!((position.bar, position.beat_in_bar), (1, 3));
+}
+
+#[test]
+fn enemy_reaching_flag_on_beat_deals_one_damage() {
+    let mut sim = Sim::new(120.0, 0);
+    sim.spawn_enemy(Vec2::new(60.0, 0.0));
+    sim.step_frames(1);
+    assert_eq!(sim.flag_health(), 8);
+
+    // steps past the flag on the beat, and stays on it between beats
+    sim.advance_to_beat(1);
+    sim.advance_to(1.5);
+    sim.step_frames(30);
+
+    assert_eq!(sim.flag_health(), 7);
+}
+
+#[test]
+fn on_beat_scribble_deals_perfect_damage() {
+    let mut sim = Sim::new(120.0, 0);
+    let enemy = sim.spawn_enemy(Vec2::new(300.0, 0.0));
+
+    sim.advance_to_beat(2);
+    sim.scribble_at(Vec2::new(300.0, 0.0));
+
+    let health = EnemyKind::Soldier.health() - Judgement::Perfect.damage();
+    assert_eq!(sim.enemy_health(enemy), health);
+    assert_eq!(
+        sim.app.world().resource::<Score>().judgements,
+        [Judgement::Perfect]
+    );
+}
+
+#[test]
+fn off_beat_scribble_misses() {
+    let mut sim = Sim::new(120.0, 0);
+    let enemy = sim.spawn_enemy(Vec2::new(300.0, 0.0));
+
+    sim.advance_to_beat(2);
+    let halfway = Duration::from_secs_f32(1.25);
+    sim.app
+        .world_mut()
+        .resource_mut::<TrackTimer>()
+        .0
+        .set_elapsed(halfway);
+    sim.scribble_at(Vec2::new(300.0, 0.0));
+
+    assert_eq!(sim.enemy_health(enemy), EnemyKind::Soldier.health());
+    assert_eq!(
+        sim.app.world().resource::<Score>().judgements,
+        [Judgement::Miss]
+    );
+}
+
+#[test]
+fn missing_into_empty_space_breaks_the_combo() {
+    let mut sim = Sim::new(120.0, 0);
+    sim.app.world_mut().resource_mut::<Score>().combo = 5;
+
+    sim.advance_to(2.5);
+    sim.scribble_at(Vec2::new(300.0, 0.0));
+
+    let score = sim.app.world().resource::<Score>();
+    assert_eq!(score.judgements, [Judgement::Miss]);
+    assert_eq!(score.combo, 0);
+}
+
+#[test]
+fn a_scribble_through_several_enemies_counts_once_towards_the_combo() {
+    let mut sim = Sim::new(120.0, 0);
+    sim.spawn_enemy(Vec2::new(300.0, 0.0));
+    sim.spawn_enemy(Vec2::new(300.0, 20.0));
+
+    sim.advance_to_beat(2);
+    sim.scribble_at(Vec2::new(300.0, 10.0));
+
+    let score = sim.app.world().resource::<Score>();
+    assert_eq!(score.combo, 1);
+    assert_eq!(score.points, 2 * Judgement::Perfect.points());
+}
+
+#[test]
+fn defeated_enemies_are_despawned_and_scored() {
+    let mut sim = Sim::new(120.0, 0);
+    let enemy = sim.spawn_enemy(Vec2::new(300.0, 0.0));
+
+    for beat in [2, 3] {
+        sim.advance_to_beat(beat);
+        let (_, pos) = sim.enemies()[0];
+        sim.scribble_at(pos);
+    }
+    sim.step_frames(1);
+
+    assert!(sim.enemies().is_empty());
+    assert_eq!(sim.app.world().resource::<Score>().enemies_defeated, 1);
+
+    // its health bar goes as it dies
+    let world = sim.app.world();
+    let children = world.get::<Children>(enemy).unwrap();
+    assert!(
+        children
+            .iter()
+            .all(|child| world.get::<HealthbarFill>(child).is_none())
+    );
+
+    // despawned along with its destination, once its death animation has played
+    let lerp_dest = *world.get::<LerpDestination>(enemy).unwrap().collection();
+    sim.advance_to_beat(4);
+    assert!(sim.app.world().get_entity(enemy).is_ok());
+
+    // playing the death frames from the atlas meanwhile
+    let world = sim.app.world();
+    let atlas_handle = &world.resource::<StartupAssetHandles>().sprite_atlas;
+    let atlas = world
+        .resource::<Assets<SpriteAtlas>>()
+        .get(atlas_handle)
+        .unwrap();
+    let rect = world.get::<Sprite>(enemy).unwrap().rect.unwrap();
+    assert!(
+        atlas
+            .get_frames("soldier dying")
+            .iter()
+            .any(|frame| frame.as_rect() == rect)
+    );
+
+    sim.advance_to_beat(6);
+    assert!(sim.app.world().get_entity(enemy).is_err());
+    assert!(sim.app.world().get_entity(lerp_dest).is_err());
+}
+
+#[test]
+fn enemies_step_towards_the_flag_on_beats() {
+    let mut sim = Sim::new(120.0, 0);
+    sim.spawn_enemy(Vec2::new(300.0, 0.0));
+
+    sim.advance_to_beat(1);
+    sim.advance_to(1.25);
+    let (_, pos) = sim.enemies()[0];
+    assert!(pos.x < 300.0 && pos.x > 225.0, "enemy at {pos}");
+
+    // arrives halfway through the beat, then waits for the next one
+    sim.advance_to(1.5);
+    sim.step_frames(60);
+    let (_, pos) = sim.enemies()[0];
+    assert!((pos.x - 225.0).abs() < 1.0, "enemy at {pos}");
+}
+
+#[test]
+fn repaired_charts_play_events_in_order() {
+    let mut sim = Sim::new(120.0, 0);
+
+    let spawn = |beat, lane| ChartEvent {
+        beat,
+        off_beat: None,
+        kind: ChartEventKind::Spawn {
+            enemy: EnemyKind::Soldier,
+            at: SpawnPoint::Lane { lane },
+        },
+    };
+    let mut chart = Chart {
+        events: vec![spawn(4, 0), spawn(2, 2)],
+    };
+    assert_eq!(chart.validate(), [ChartIssue::UnsortedEvents(1)]);
+
+    chart.repair();
+    assert!(chart.validate().is_empty());
+
+    let world = sim.app.world_mut();
+    let chart = world.resource_mut::<Assets<Chart>>().add(chart);
+    world.resource_mut::<CurrentSong>().chart = Some(chart);
+
+    sim.advance_to_beat(3);
+    let enemies = sim.enemies();
+    assert_eq!(enemies.len(), 1);
+    // lane 2 is straight up
+    assert!(enemies[0].1.normalize().distance(Vec2::Y) < 0.01);
+}
+
+#[test]
+fn random_spawns_repeat_with_the_same_seed() {
+    let spawns = |seed| {
+        let mut sim = Sim::new(120.0, seed);
+        // spawns happen on downbeats after the first bar
+        for beat in 1..=12 {
+            sim.advance_to_beat(beat);
+        }
+        sim.enemies()
+            .into_iter()
+            .map(|(_, pos)| pos)
+            .collect::<Vec<_>>()
+    };
+
+    let first = spawns(7);
+    assert_eq!(first.len(), 3);
+    assert_eq!(first, spawns(7));
+    assert_ne!(first, spawns(8));
+}
+
+#[test]
+fn losing_the_flag_ends_the_run() {
+    let mut sim = Sim::new(120.0, 0);
+    sim.spawn_enemy(Vec2::new(60.0, 0.0));
+
+    let world = sim.app.world_mut();
+    let mut flags = world.query_filtered::<&mut Health, With<Flag>>();
+    flags.single_mut(world).unwrap().remaining = 1;
+
+    sim.advance_to_beat(1);
+    sim.advance_to(1.5);
+    sim.step_frames(2);
+
+    let screen = sim.app.world().resource::<State<Screen>>();
+    assert_eq!(*screen.get(), Screen::GameOver);
+}
+
+/// seconds between samples of a trajectory, a whole number of frames at each tested frame rate
+const SAMPLE_INTERVAL: f32 = 1.0 / 6.0;
+const FRAME_RATES: [f32; 3] = [30.0, 60.0, 144.0];
+
+#[test]
+fn enemy_steps_are_frame_rate_independent() {
+    let trajectory = |frame_rate| {
+        let mut sim = Sim::new(120.0, 0);
+        sim.spawn_enemy(Vec2::new(300.0, 100.0));
+
+        (0..6)
+            .map(|_| {
+                sim.play(SAMPLE_INTERVAL, frame_rate);
+                sim.enemies()[0].1
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let expected = trajectory(60.0);
+    assert!(
+        expected.last().unwrap().length() < 250.0,
+        "enemy never moved"
+    );
+    for frame_rate in FRAME_RATES {
+        for (pos, expected) in trajectory(frame_rate).into_iter().zip(&expected) {
+            assert!(
+                pos.distance(*expected) < 0.01,
+                "at {frame_rate} fps, enemy at {pos} instead of {expected}"
+            );
+        }
+    }
+}
+
+#[test]
+fn steps_arriving_at_the_boundary_carry_on_without_snapping() {
+    for frame_rate in FRAME_RATES {
+        let mut sim = Sim::new(120.0, 0);
+        let world = sim.app.world_mut();
+        let follower = world
+            .spawn((
+                BeatLerp::new(Subdivision::Beat, EaseFunction::Linear, 1.0),
+                Transform::default(),
+            ))
+            .id();
+        world.spawn((Transform::default(), LerpDestinationOf(follower)));
+
+        // one step ahead every beat, so the follower should move at a steady 100 per beat
+        sim.app.add_systems(
+            Update,
+            (|beat_index: Res<BeatIndex>,
+              mut destinations: Query<&mut Transform, With<LerpDestinationOf>>| {
+                for mut transform in &mut destinations {
+                    transform.translation.x = 100.0 * (beat_index.0 + 1) as f32;
+                }
+            })
+            .in_set(MovementSet)
+            .before(LerpSet),
+        );
+
+        let mut last_x = 0.0;
+        let frames = (2.5 * frame_rate) as usize;
+        for _ in 0..frames {
+            sim.play(1.0 / frame_rate, frame_rate);
+
+            let x = sim
+                .app
+                .world()
+                .get::<Transform>(follower)
+                .unwrap()
+                .translation
+                .x;
+            let track_time = sim.app.world().resource::<TrackTimer>().0.elapsed_secs();
+            let expected = 100.0 * track_time / 0.5;
+            assert!(
+                (x - expected).abs() < 0.1,
+                "at {frame_rate} fps, {x} instead of {expected} at {track_time}s"
+            );
+
+            // no more than a frame's worth of movement, even across steps
+            let max_move = 100.0 / 0.5 / frame_rate;
+            assert!(x - last_x < max_move + 0.1, "jumped from {last_x} to {x}");
+            last_x = x;
+        }
+    }
+}
+
+#[test]
+fn smoothing_is_frame_rate_independent() {
+    let half_life = 0.1;
+
+    for frame_rate in FRAME_RATES {
+        let mut sim = Sim::new(120.0, 0);
+        let world = sim.app.world_mut();
+        let follower = world
+            .spawn((SmoothLerp { half_life }, Transform::default()))
+            .id();
+        world.spawn((
+            Transform::from_xyz(100.0, 0.0, 0.0),
+            LerpDestinationOf(follower),
+        ));
+
+        for sample in 1..=6 {
+            sim.play(SAMPLE_INTERVAL, frame_rate);
+
+            let elapsed = sample as f32 * SAMPLE_INTERVAL;
+            let expected = 100.0 * (1.0 - 0.5_f32.powf(elapsed / half_life));
+            let x = sim
+                .app
+                .world()
+                .get::<Transform>(follower)
+                .unwrap()
+                .translation
+                .x;
+            assert!(
+                (x - expected).abs() < 0.01,
+                "at {frame_rate} fps, {x} instead of {expected} after {elapsed}s"
+            );
+        }
+    }
+}
+
+#[test]
+fn replays_score_the_same_at_any_frame_rate() {
+    // near the edges of the timing windows, where a frame either way changes the judgement
+    let press_times = [0.53, 0.92, 1.61, 1.75];
+
+    let run = |frame_rate: f32, replay: Option<Replay>| {
+        let mut sim = Sim::new(120.0, 0);
+        sim.spawn_enemy(Vec2::new(300.0, 0.0));
+        sim.move_reticle(Vec2::new(225.0, 0.0));
+
+        match replay {
+            Some(replay) => {
+                sim.app
+                    .insert_resource(ReplayPlayback {
+                        replay,
+                        last_track_time: 0.0,
+                        player_offsets: (0.0, 0.0),
+                    })
+                    .add_systems(Update, replay_input.in_set(InputSet));
+                sim.play(2.5, frame_rate);
+            }
+            None => {
+                sim.app
+                    .init_resource::<Recording>()
+                    .add_systems(Update, record_input.in_set(InputSet));
+                sim.play_scribbling(2.5, frame_rate, &press_times);
+            }
+        }
+
+        let world = sim.app.world_mut();
+        let replay = world
+            .remove_resource::<Recording>()
+            .map(|recording| recording.0);
+        let score = world.remove_resource::<Score>().unwrap();
+        let summary = (
+            score.points,
+            score.combo,
+            score.max_combo,
+            score.enemies_defeated,
+            score.judgements,
+        );
+        (summary, replay)
+    };
+
+    let (recorded_score, replay) = run(60.0, None);
+    assert_eq!(recorded_score.4.len(), press_times.len());
+    assert!(recorded_score.0 > 0);
+
+    let (replayed_score, _) = run(20.0, replay);
+    assert_eq!(replayed_score, recorded_score);
+}
+
+#[test]
+fn escape_still_pauses_after_rebinding_pause() {
+    let mut sim = Sim::new(120.0, 0);
+    let world = sim.app.world_mut();
+    world
+        .resource_mut::<Settings>()
+        .bindings
+        .bind(Action::Pause, Binding::Key(KeyCode::KeyP));
+    world.init_resource::<ButtonInput<KeyCode>>();
+    world.init_resource::<ButtonInput<MouseButton>>();
+    world.init_resource::<Touches>();
+
+    for key in [KeyCode::KeyP, KeyCode::Escape] {
+        let world = sim.app.world_mut();
+        *world.resource_mut::<NextState<Pause>>() = NextState::Unchanged;
+        let mut keyboard = world.resource_mut::<ButtonInput<KeyCode>>();
+        keyboard.reset_all();
+        keyboard.press(key);
+
+        world.run_system_once(toggle_pause).unwrap();
+        assert!(
+            matches!(
+                *world.resource::<NextState<Pause>>(),
+                NextState::Pending(Pause::Paused)
+            ),
+            "{key:?} didn't pause"
+        );
+    }
+}
+
+#[test]
+fn replays_with_unplayable_frames_are_rejected() {
+    let replay = |times: &str| {
+        let frames = times
+            .split(',')
+            .map(|time| {
+                format!(
+                    r#"{{"track_time": {time}, "aim_pos": [0, 0], "quill_down": false, "quill_pressed": false}}"#
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let json = format!(
+            r#"{{"seed": 0, "audio": "", "beats": "", "chart": null, "audio_offset": 0, "input_offset": 0, "frames": [{frames}]}}"#
+        );
+        Replay::from_json(&json)
+    };
+
+    assert!(replay("0.0, 0.5, 0.5, 1.0").is_ok());
+    assert!(replay("0.0, 1.0, 0.5").is_err());
+    // NaN is saved as null
+    assert!(replay("0.0, null").is_err());
+    assert!(replay("0.0, 1e999").is_err());
+}
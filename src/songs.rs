@@ -0,0 +1,168 @@
+//! The song library and song select screen
+
+use bevy::prelude::*;
+use bevy_kira_audio::AudioSource;
+
+use crate::Screen;
+use crate::assets::StartupAssetHandles;
+use crate::beats::Beats;
+use crate::chart::Chart;
+use crate::ui::{menu_button, return_to_menu};
+
+pub(super) fn plugin(app: &mut App) {
+    app.insert_resource(SongDestination(Screen::InGame))
+        .add_systems(OnEnter(Screen::SongSelect), spawn_song_select)
+        .add_systems(OnEnter(Screen::SongLoading), spawn_song_loading_text)
+        .add_systems(Update, wait_for_song.run_if(in_state(Screen::SongLoading)));
+}
+
+/// All of the playable songs, listed in `library.songs.json`
+#[derive(serde::Serialize, serde::Deserialize, bevy::asset::Asset, bevy::reflect::TypePath)]
+pub(super) struct SongLibrary {
+    pub(super) songs: Vec<SongEntry>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub(super) struct SongEntry {
+    title: String,
+    /// asset path of the track audio
+    audio: String,
+    /// asset path of the track's beats.json
+    pub(super) beats: String,
+    /// asset path of the track's chart.json; enemies spawn randomly without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) chart: Option<String>,
+    difficulty: Difficulty,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+/// the screen to continue to once the selected song has loaded
+#[derive(Resource)]
+pub(super) struct SongDestination(pub(super) Screen);
+
+/// handles to the assets of the selected song
+#[derive(Resource)]
+pub(super) struct CurrentSong {
+    pub(super) audio: Handle<AudioSource>,
+    pub(super) beats: Handle<Beats>,
+    pub(super) chart: Option<Handle<Chart>>,
+}
+
+fn spawn_song_select(
+    mut commands: Commands,
+    assets: Res<StartupAssetHandles>,
+    libraries: Res<Assets<SongLibrary>>,
+) {
+    let library = libraries.get(&assets.song_library).unwrap();
+    let songs = library.songs.clone();
+
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(24.0),
+            ..default()
+        },
+        GlobalZIndex(2),
+        DespawnOnExit(Screen::SongSelect),
+        Pickable::IGNORE,
+        Children::spawn(SpawnWith(move |parent: &mut ChildSpawner| {
+            parent.spawn((
+                Text::new("Select a song"),
+                TextFont {
+                    font_size: 48.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.15, 0.15, 0.15)),
+            ));
+
+            for song in songs {
+                let label = format!("{} ({:?})", song.title, song.difficulty);
+                parent.spawn(menu_button(label)).observe(
+                    move |_on_click: On<Pointer<Click>>,
+                          mut commands: Commands,
+                          asset_server: Res<AssetServer>,
+                          mut next_screen: ResMut<NextState<Screen>>| {
+                        commands.insert_resource(CurrentSong {
+                            audio: asset_server.load(&song.audio),
+                            beats: asset_server.load(&song.beats),
+                            chart: song.chart.as_ref().map(|chart| asset_server.load(chart)),
+                        });
+                        next_screen.set(Screen::SongLoading);
+                    },
+                );
+            }
+
+            parent.spawn(menu_button("Back")).observe(return_to_menu);
+        })),
+    ));
+}
+
+fn spawn_song_loading_text(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        GlobalZIndex(2),
+        DespawnOnExit(Screen::SongLoading),
+        Pickable::IGNORE,
+        children![(
+            Text::new("loading..."),
+            TextFont {
+                font_size: 32.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.15, 0.15, 0.15)),
+        )],
+    ));
+}
+
+fn wait_for_song(
+    asset_server: Res<AssetServer>,
+    mut song: ResMut<CurrentSong>,
+    destination: Res<SongDestination>,
+    mut next_screen: ResMut<NextState<Screen>>,
+) {
+    let failed = asset_server.load_state(&song.audio).is_failed()
+        || asset_server.load_state(&song.beats).is_failed();
+    if failed {
+        log::error!("failed to load song");
+        next_screen.set(Screen::SongSelect);
+        return;
+    }
+
+    let chart_failed = song
+        .chart
+        .as_ref()
+        .is_some_and(|chart| asset_server.load_state(chart).is_failed());
+    if chart_failed {
+        // dropping the chart falls back to random spawns, and keeps this from warning every frame
+        log::warn!("failed to load chart; spawning enemies randomly");
+        song.chart = None;
+    }
+
+    let chart_loaded = song
+        .chart
+        .as_ref()
+        .is_none_or(|chart| asset_server.is_loaded_with_dependencies(chart));
+    let loaded = asset_server.is_loaded_with_dependencies(&song.audio)
+        && asset_server.is_loaded_with_dependencies(&song.beats)
+        && chart_loaded;
+    if loaded {
+        next_screen.set(destination.0.clone());
+    }
+}
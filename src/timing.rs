@@ -0,0 +1,393 @@
+//! The music clock: playing the track, and deriving beats, bars and subdivisions from it
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_kira_audio::{Audio, AudioControl, AudioInstance, AudioSource, AudioTween, PlaybackState};
+
+use crate::beats::Beats;
+use crate::settings::Settings;
+use crate::songs::CurrentSong;
+use crate::{BeatSet, Screen};
+
+/// playing the current track, and keeping the track timer in step with it
+pub(super) fn track_plugin(app: &mut App) {
+    app.add_systems(OnEnter(Screen::InGame), (play_track, init_track_timer))
+        .add_systems(OnExit(Screen::InGame), stop_track)
+        .add_systems(Update, tick_track_timer.in_set(BeatSet::Track));
+}
+
+/// the playing instance of the current track, used as the source of truth for musical time
+#[derive(Resource)]
+pub(super) struct TrackInstance {
+    pub(super) handle: Handle<AudioInstance>,
+    /// How many frames in a row the track timer has been past [`TRACK_RESYNC_THRESHOLD`] from the audio
+    ///
+    /// Kept with the instance rather than in `tick_track_timer`, so seeking can clear it and a new
+    /// track never starts with a stale count.
+    pub(super) desynced_frames: u32,
+}
+
+impl TrackInstance {
+    pub(super) fn new(handle: Handle<AudioInstance>) -> Self {
+        Self {
+            handle,
+            desynced_frames: 0,
+        }
+    }
+}
+
+fn play_track(mut commands: Commands, song: Res<CurrentSong>, audio: Res<Audio>) {
+    let instance = audio.play(song.audio.clone()).handle();
+    commands.insert_resource(TrackInstance::new(instance));
+}
+
+pub(super) fn stop_track(
+    mut commands: Commands,
+    track_instance: Option<Res<TrackInstance>>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    let Some(track_instance) = track_instance else {
+        return;
+    };
+
+    if let Some(instance) = audio_instances.get_mut(&track_instance.handle) {
+        instance.stop(AudioTween::linear(Duration::from_millis(500)));
+    }
+
+    commands.remove_resource::<TrackInstance>();
+}
+
+/// the music clock: beats, bars and subdivisions, derived from the track timer
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<TrackTimer>()
+        .init_resource::<BeatIndex>()
+        .init_resource::<BeatTimer>()
+        .init_resource::<OnBeat>()
+        .init_resource::<MusicalPosition>()
+        .init_resource::<Subdivisions>()
+        .add_message::<OnSubdivision>()
+        .add_systems(OnEnter(Screen::InGame), init_beat_timer)
+        .add_systems(OnExit(Screen::InGame), reset_timing)
+        .add_systems(
+            Update,
+            (tick_beat_timer, update_musical_position, send_subdivisions)
+                .chain()
+                .in_set(BeatSet::Tick),
+        );
+}
+
+/// resets the per-run timing state, so the next run starts clean
+fn reset_timing(
+    mut beat_index: ResMut<BeatIndex>,
+    mut beat_timer: ResMut<BeatTimer>,
+    mut track_timer: ResMut<TrackTimer>,
+    mut on_beat: ResMut<OnBeat>,
+    mut position: ResMut<MusicalPosition>,
+) {
+    *beat_index = BeatIndex::default();
+    *beat_timer = BeatTimer::default();
+    *track_timer = TrackTimer::default();
+    *on_beat = OnBeat::default();
+    *position = MusicalPosition::default();
+}
+
+/// Elapsed time of the current music track
+#[derive(Resource, Default)]
+pub(super) struct TrackTimer(pub(super) Timer);
+
+impl TrackTimer {
+    pub(super) fn new(track_length: Duration) -> Self {
+        let timer = Timer::new(track_length, TimerMode::Once);
+
+        Self(timer)
+    }
+}
+
+fn init_track_timer(
+    song: Res<CurrentSong>,
+    audio_sources: Res<Assets<AudioSource>>,
+    beats_assets: Res<Assets<Beats>>,
+    mut track_timer: ResMut<TrackTimer>,
+) {
+    let track_length = match audio_sources.get(&song.audio) {
+        Some(source) => source.sound.duration(),
+        None => {
+            log::warn!("track audio missing; using beats for track length");
+            let beats = beats_assets.get(&song.beats).unwrap();
+            let last_beat = beats.beats.last().copied().unwrap_or_default();
+            Duration::from_secs_f32(last_beat + 1.0)
+        }
+    };
+
+    *track_timer = TrackTimer::new(track_length);
+}
+
+/// how much of the measured drift from the audio clock to correct per frame
+const TRACK_DRIFT_CORRECTION: f32 = 0.1;
+/// drift (in seconds) past which the track timer snaps directly to the audio clock
+const TRACK_RESYNC_THRESHOLD: f32 = 0.25;
+/// consecutive frames past the threshold before resyncing,
+/// since the reported position lags behind seeks
+const TRACK_RESYNC_FRAMES: u32 = 3;
+
+/// Advances the track timer by the frame delta, then steers it towards the
+/// playback position reported by kira.
+///
+/// The reported position only updates once per audio buffer, so following it
+/// directly would stutter; the frame delta keeps the timer smooth in between.
+fn tick_track_timer(
+    time: Res<Time>,
+    track_instance: Option<ResMut<TrackInstance>>,
+    audio_instances: Res<Assets<AudioInstance>>,
+    mut track_timer: ResMut<TrackTimer>,
+) {
+    let Some(mut track_instance) = track_instance else {
+        track_timer.0.tick(time.delta());
+        return;
+    };
+
+    let playback_state = audio_instances
+        .get(&track_instance.handle)
+        .map(|instance| instance.state());
+
+    // don't start the clock until the audio has actually started
+    if let Some(PlaybackState::Queued) = playback_state {
+        return;
+    }
+
+    track_timer.0.tick(time.delta());
+
+    let Some(audio_pos) = playback_state.and_then(|state| state.position()) else {
+        return;
+    };
+
+    let audio_pos = audio_pos as f32;
+    let timer_pos = track_timer.0.elapsed_secs();
+    let drift = audio_pos - timer_pos;
+
+    if drift.abs() <= TRACK_RESYNC_THRESHOLD {
+        track_instance.desynced_frames = 0;
+    } else {
+        track_instance.desynced_frames += 1;
+        if track_instance.desynced_frames < TRACK_RESYNC_FRAMES {
+            return;
+        }
+    }
+
+    let corrected_pos = if track_instance.desynced_frames > 0 {
+        log::debug!("resyncing track timer; drifted {drift:.3}s from audio");
+        track_instance.desynced_frames = 0;
+        audio_pos
+    } else {
+        timer_pos + drift * TRACK_DRIFT_CORRECTION
+    };
+
+    track_timer
+        .0
+        .set_elapsed(Duration::from_secs_f32(corrected_pos.max(0.0)));
+}
+
+#[derive(Resource, Default)]
+pub(super) struct BeatIndex(pub(super) usize);
+
+#[derive(Resource, Default)]
+struct BeatTimer(Timer);
+
+impl BeatTimer {
+    fn from_index(beat_index: usize, track_time: f32, beats: &Beats) -> Self {
+        // the start of the track counts as the first beat
+        let prev_beat = if beat_index == 0 {
+            0.0
+        } else {
+            beats.beat_time(beat_index).min(track_time)
+        };
+        let next_beat = beats.beat_time(beat_index + 1);
+
+        // guard against zero-length beats in unrepaired assets
+        let duration = (next_beat - prev_beat).max(0.001);
+        let mut timer = Timer::new(Duration::from_secs_f32(duration), TimerMode::Once);
+        timer.set_elapsed(Duration::from_secs_f32(track_time - prev_beat));
+
+        Self(timer)
+    }
+
+    // how far through the beat we are, 0.0-1.0
+    fn elapsed_ratio(&self) -> f32 {
+        self.0.elapsed_secs() / self.0.duration().as_secs_f32()
+    }
+}
+
+fn init_beat_timer(
+    song: Res<CurrentSong>,
+    beats_assets: Res<Assets<Beats>>,
+    mut beat_timer: ResMut<BeatTimer>,
+) {
+    let beats = beats_assets.get(&song.beats).unwrap();
+    *beat_timer = BeatTimer::from_index(0, 0.0, beats);
+}
+
+#[derive(Resource, Default)]
+pub(super) struct OnBeat(pub(super) bool);
+
+/// Where the music is, in bars and beats
+#[derive(Resource, Default, Debug, Clone, Copy)]
+pub(super) struct MusicalPosition {
+    /// counted from 1 at the first downbeat; 0 is the pickup
+    pub(super) bar: usize,
+    /// 0 on the downbeat
+    pub(super) beat_in_bar: usize,
+    /// how far through the current beat we are, 0.0-1.0
+    pub(super) fraction: f32,
+    pub(super) beats_per_bar: usize,
+}
+
+impl MusicalPosition {
+    pub(super) fn is_downbeat(&self) -> bool {
+        self.beat_in_bar == 0
+    }
+
+    /// how far through the current bar we are, 0.0-1.0
+    pub(super) fn bar_phase(&self) -> f32 {
+        (self.beat_in_bar as f32 + self.fraction) / self.beats_per_bar.max(1) as f32
+    }
+
+    /// whether the current beat starts a run of `beats` beats, counting from the downbeats
+    ///
+    /// Runs that divide the bar restart on every downbeat, so a pickup bar can't shift them.
+    pub(super) fn starts_every(&self, beats: usize) -> bool {
+        let beat = self.bar * self.beats_per_bar + self.beat_in_bar;
+        beat.is_multiple_of(beats.max(1))
+    }
+
+    /// which step of the given subdivision the current beat is on, 0 on the beat
+    pub(super) fn subdivision_step(&self, subdivision: Subdivision) -> usize {
+        let steps = subdivision.steps_per_beat();
+        ((self.fraction * steps as f32) as usize).min(steps - 1)
+    }
+}
+
+/// Rises from 0.0 to 1.0 at `peak`, then falls back to 0.0 by the end of the phase,
+/// shaped by an easing curve on each side
+pub(super) fn ease_pulse(phase: f32, peak: f32, rise: EaseFunction, fall: EaseFunction) -> f32 {
+    let peak = peak.clamp(0.01, 0.99);
+    if phase < peak {
+        rise.sample_clamped(phase / peak)
+    } else {
+        1.0 - fall.sample_clamped((phase - peak) / (1.0 - peak))
+    }
+}
+
+/// An even division of the beat
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum Subdivision {
+    /// just the beat itself
+    Beat,
+    Eighth,
+    Triplet,
+    Sixteenth,
+}
+
+impl Subdivision {
+    pub(super) fn steps_per_beat(self) -> usize {
+        match self {
+            Subdivision::Beat => 1,
+            Subdivision::Eighth => 2,
+            Subdivision::Triplet => 3,
+            Subdivision::Sixteenth => 4,
+        }
+    }
+}
+
+/// which subdivisions send [`OnSubdivision`] messages
+#[derive(Resource)]
+struct Subdivisions(Vec<Subdivision>);
+
+impl Default for Subdivisions {
+    fn default() -> Self {
+        Self(vec![
+            Subdivision::Eighth,
+            Subdivision::Triplet,
+            Subdivision::Sixteenth,
+        ])
+    }
+}
+
+/// sent when the music reaches a step of a subdivision, including the beat itself
+#[derive(Message, Debug, Clone, Copy)]
+pub(super) struct OnSubdivision {
+    pub(super) subdivision: Subdivision,
+    /// 0 on the beat
+    step: usize,
+}
+
+impl OnSubdivision {
+    pub(super) fn is_off_beat(&self) -> bool {
+        self.step != 0
+    }
+}
+
+/// Derives the beat index and beat timer from the (audio-synced) track timer,
+/// rather than ticking them separately, so they can't drift from the music.
+fn tick_beat_timer(
+    track_timer: Res<TrackTimer>,
+    settings: Res<Settings>,
+    song: Res<CurrentSong>,
+    beats_assets: Res<Assets<Beats>>,
+    mut beat_index: ResMut<BeatIndex>,
+    mut beat_timer: ResMut<BeatTimer>,
+    mut on_beat: ResMut<OnBeat>,
+) {
+    let beats = beats_assets.get(&song.beats).unwrap();
+    let track_time = settings.heard_track_time(&track_timer).max(0.0);
+
+    let start_index = beat_index.0;
+    // past the last beat, this keeps counting at the final tempo
+    while track_time >= beats.beat_time(beat_index.0 + 1) {
+        beat_index.0 += 1;
+    }
+
+    let skipped = beat_index.0.saturating_sub(start_index + 1);
+    if skipped > 0 {
+        log::debug!("skipped {skipped} beats");
+    }
+
+    on_beat.0 = beat_index.0 != start_index;
+    *beat_timer = BeatTimer::from_index(beat_index.0, track_time, beats);
+}
+
+fn update_musical_position(
+    song: Res<CurrentSong>,
+    beats_assets: Res<Assets<Beats>>,
+    beat_index: Res<BeatIndex>,
+    beat_timer: Res<BeatTimer>,
+    mut position: ResMut<MusicalPosition>,
+) {
+    let beats = beats_assets.get(&song.beats).unwrap();
+    let (bar, beat_in_bar) = beats.bar_position(beat_index.0);
+
+    *position = MusicalPosition {
+        bar,
+        beat_in_bar,
+        fraction: beat_timer.elapsed_ratio(),
+        beats_per_bar: beats.time_signature.beats_per_bar,
+    };
+}
+
+fn send_subdivisions(
+    subdivisions: Res<Subdivisions>,
+    beat_index: Res<BeatIndex>,
+    position: Res<MusicalPosition>,
+    mut last_steps: Local<HashMap<Subdivision, (usize, usize)>>,
+    mut on_subdivision: MessageWriter<OnSubdivision>,
+) {
+    for &subdivision in &subdivisions.0 {
+        let step = position.subdivision_step(subdivision);
+        let current = (beat_index.0, step);
+        if last_steps.insert(subdivision, current) != Some(current) {
+            on_subdivision.write(OnSubdivision { subdivision, step });
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! The camera, menus and end screens
+
+use bevy::color::palettes::tailwind;
+use bevy::prelude::*;
+
+use crate::Screen;
+use crate::combat::Judgement;
+use crate::replay::{DailyChallenge, GameRng};
+use crate::score::{Score, seed_label};
+use crate::songs::SongDestination;
+
+pub(super) fn plugin(app: &mut App) {
+    app.insert_resource(ClearColor(Color::Srgba(tailwind::GRAY_200)))
+        .add_systems(OnEnter(Screen::Loading), spawn_camera)
+        .add_systems(OnEnter(Screen::Menu), spawn_main_menu)
+        .add_systems(OnEnter(Screen::GameOver), spawn_game_over_screen)
+        .add_systems(OnEnter(Screen::Victory), spawn_victory_screen);
+}
+
+fn spawn_camera(mut commands: Commands) {
+    commands.spawn(Camera2d);
+}
+
+fn spawn_game_over_screen(
+    mut commands: Commands,
+    score: Res<Score>,
+    rng: Res<GameRng>,
+    daily: Res<DailyChallenge>,
+) {
+    let summary = format!(
+        "score: {}\nenemies defeated: {}\n{}",
+        score.points,
+        score.enemies_defeated,
+        seed_label(rng.seed, daily.0),
+    );
+
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(40.0),
+            ..default()
+        },
+        GlobalZIndex(2),
+        DespawnOnExit(Screen::GameOver),
+        Pickable::IGNORE,
+        Children::spawn(SpawnWith(move |parent: &mut ChildSpawner| {
+            parent.spawn((
+                Text::new("Game Over"),
+                TextFont {
+                    font_size: 72.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.15, 0.15, 0.15)),
+            ));
+            parent.spawn((
+                Text::new(summary),
+                TextFont {
+                    font_size: 28.0,
+                    ..default()
+                },
+                TextLayout::new_with_justify(Justify::Center),
+                TextColor(Color::srgb(0.15, 0.15, 0.15)),
+            ));
+            parent.spawn(menu_button("Retry")).observe(start_game);
+            parent.spawn(menu_button("Menu")).observe(return_to_menu);
+        })),
+    ));
+}
+
+fn spawn_victory_screen(
+    mut commands: Commands,
+    score: Res<Score>,
+    rng: Res<GameRng>,
+    daily: Res<DailyChallenge>,
+) {
+    let summary = format!(
+        "score: {}\n\
+         max combo: {}\n\
+         accuracy: {:.1}%\n\
+         perfect {}  great {}  good {}  miss {}\n\
+         enemies defeated: {}\n\
+         {}",
+        score.points,
+        score.max_combo,
+        score.accuracy() * 100.0,
+        score.count(Judgement::Perfect),
+        score.count(Judgement::Great),
+        score.count(Judgement::Good),
+        score.count(Judgement::Miss),
+        score.enemies_defeated,
+        seed_label(rng.seed, daily.0),
+    );
+
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(40.0),
+            ..default()
+        },
+        GlobalZIndex(2),
+        DespawnOnExit(Screen::Victory),
+        Pickable::IGNORE,
+        Children::spawn(SpawnWith(move |parent: &mut ChildSpawner| {
+            parent.spawn((
+                Text::new("Victory"),
+                TextFont {
+                    font_size: 72.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.15, 0.15, 0.15)),
+            ));
+            parent.spawn((
+                Text::new(summary),
+                TextFont {
+                    font_size: 28.0,
+                    ..default()
+                },
+                TextLayout::new_with_justify(Justify::Center),
+                TextColor(Color::srgb(0.15, 0.15, 0.15)),
+            ));
+            parent.spawn(menu_button("Menu")).observe(return_to_menu);
+        })),
+    ));
+}
+
+fn spawn_main_menu(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(40.0),
+            ..default()
+        },
+        GlobalZIndex(2),
+        DespawnOnExit(Screen::Menu),
+        Pickable::IGNORE,
+        Children::spawn(SpawnWith(|parent: &mut ChildSpawner| {
+            // Title
+            parent.spawn((
+                Text::new("vivace"),
+                TextFont {
+                    font_size: 72.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.15, 0.15, 0.15)),
+            ));
+            parent.spawn(menu_button("Play")).observe(start_song_select);
+            parent
+                .spawn(menu_button("Daily Challenge"))
+                .observe(start_daily_challenge);
+            parent
+                .spawn(menu_button("Calibrate"))
+                .observe(start_calibration_screen);
+            parent
+                .spawn(menu_button("Controls"))
+                .observe(start_controls_screen);
+            #[cfg(feature = "dev")]
+            parent
+                .spawn(menu_button("Editor"))
+                .observe(crate::editor::start_editor_song_select);
+        })),
+    ));
+}
+
+pub(super) fn menu_button(label: impl Into<String>) -> impl Bundle {
+    (
+        Button,
+        Node {
+            min_width: Val::Px(200.0),
+            height: Val::Px(65.0),
+            padding: UiRect::horizontal(Val::Px(24.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            border: UiRect::all(Val::Px(3.0)),
+            border_radius: BorderRadius::MAX,
+            ..default()
+        },
+        BorderColor::all(Color::srgb(0.15, 0.15, 0.15)),
+        BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+        children![(
+            Text::new(label),
+            TextFont {
+                font_size: 32.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+            Pickable::IGNORE,
+        )],
+    )
+}
+
+fn start_game(_on_click: On<Pointer<Click>>, mut next_screen: ResMut<NextState<Screen>>) {
+    next_screen.set(Screen::InGame)
+}
+
+fn start_song_select(
+    _on_click: On<Pointer<Click>>,
+    mut destination: ResMut<SongDestination>,
+    mut daily: ResMut<DailyChallenge>,
+    mut next_screen: ResMut<NextState<Screen>>,
+) {
+    daily.0 = false;
+    destination.0 = Screen::InGame;
+    next_screen.set(Screen::SongSelect)
+}
+
+fn start_daily_challenge(
+    _on_click: On<Pointer<Click>>,
+    mut destination: ResMut<SongDestination>,
+    mut daily: ResMut<DailyChallenge>,
+    mut next_screen: ResMut<NextState<Screen>>,
+) {
+    daily.0 = true;
+    destination.0 = Screen::InGame;
+    next_screen.set(Screen::SongSelect)
+}
+
+fn start_calibration_screen(
+    _on_click: On<Pointer<Click>>,
+    mut next_screen: ResMut<NextState<Screen>>,
+) {
+    next_screen.set(Screen::Calibration)
+}
+
+fn start_controls_screen(
+    _on_click: On<Pointer<Click>>,
+    mut next_screen: ResMut<NextState<Screen>>,
+) {
+    next_screen.set(Screen::Controls)
+}
+
+pub(super) fn return_to_menu(
+    _on_click: On<Pointer<Click>>,
+    mut next_screen: ResMut<NextState<Screen>>,
+) {
+    next_screen.set(Screen::Menu)
+}